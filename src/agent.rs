@@ -0,0 +1,208 @@
+use std::{
+    collections::BTreeMap,
+    io::{ErrorKind, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use eyre::{eyre, OptionExt, Result, WrapErr};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use crate::storage::Storage;
+
+/// Messages larger than this are rejected, so a bad peer can't make us allocate without bound.
+const MAX_MESSAGE_LEN: u32 = 16 * 1024 * 1024;
+
+/// A request sent from `bwrs` to `bwrsd`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    Ping,
+    /// Hand the agent an account's decrypted keys.
+    Unlock {
+        account: String,
+        /// The base 64 encoded user key.
+        user_key: Zeroizing<String>,
+        /// Base 64 encoded organization keys, by organization id.
+        #[serde(default)]
+        org_keys: BTreeMap<Uuid, Zeroizing<String>>,
+    },
+    /// Ask for an account's keys, if the agent holds them. This counts as activity for the
+    /// account's idle timeout.
+    GetKeys {
+        account: String,
+    },
+    /// Ask whether the agent holds an account's keys, without counting as activity. Answered
+    /// with [`Response::Ok`] or [`Response::Locked`].
+    HasKeys {
+        account: String,
+    },
+    /// Drop the keys for one account, or every account if `None`.
+    Lock {
+        account: Option<String>,
+    },
+    /// Keep the connection open and receive [`Response::Event`]s as they happen. This is only
+    /// for other programs, such as scripts waiting for login requests to approve: `bwrs` never
+    /// subscribes, since each command reads the vault cache afresh.
+    Subscribe,
+}
+
+/// A response sent from `bwrsd` to `bwrs`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Pong,
+    Ok,
+    Keys {
+        user_key: Zeroizing<String>,
+        org_keys: BTreeMap<Uuid, Zeroizing<String>>,
+    },
+    Locked,
+    Error {
+        message: String,
+    },
+    Event(Event),
+}
+
+/// Something that happened in the agent, pushed to programs which sent [`Request::Subscribe`].
+/// Nothing in `bwrs` reacts to these.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The agent was handed the account's keys.
+    Unlocked { account: String },
+    /// The agent dropped the account's keys, on request or because a timeout passed.
+    Locked { account: String },
+    /// The account's vault cache was replaced with newer data.
+    VaultChanged { account: String },
+    /// The server ended the account's session, so its keys and cache were dropped.
+    LoggedOut { account: String },
+    /// Another device asked to be logged in with the account, and is waiting for approval.
+    AuthRequest { account: String, id: Uuid },
+}
+
+/// The socket `bwrsd` listens on: `$BWRS_AGENT_SOCK`, or `bwrs/agent.sock` in `$XDG_RUNTIME_DIR`.
+pub fn socket_path() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os("BWRS_AGENT_SOCK") {
+        return Ok(PathBuf::from(path));
+    }
+
+    Ok(dirs::runtime_dir()
+        .ok_or_eyre("$XDG_RUNTIME_DIR is not set")?
+        .join(env!("CARGO_PKG_NAME"))
+        .join("agent.sock"))
+}
+
+/// The pidfile `bwrsd` holds a lock on while running, next to its socket.
+pub fn pid_path() -> Result<PathBuf> {
+    Ok(socket_path()?.with_file_name("bwrsd.pid"))
+}
+
+/// The socket `bwrsd --ssh-agent` serves the SSH agent protocol on, next to the agent socket.
+pub fn ssh_socket_path() -> Result<PathBuf> {
+    Ok(socket_path()?.with_file_name("ssh-agent.sock"))
+}
+
+/// Write a message, prefixed with its length as a big-endian `u32`. Messages can carry keys, so
+/// the serialized copy is zeroized once sent.
+pub fn write_message<T: Serialize>(stream: &mut impl Write, message: &T) -> Result<()> {
+    let body = Zeroizing::new(serde_json::to_vec(message)?);
+    let len = u32::try_from(body.len())
+        .ok()
+        .filter(|len| *len <= MAX_MESSAGE_LEN)
+        .ok_or_eyre("Message is too large")?;
+
+    stream.write_all(&len.to_be_bytes())?;
+    stream.write_all(&body)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Read a length-prefixed message, returning `None` if the peer closed the connection. The raw
+/// bytes are zeroized once parsed.
+pub fn read_message<T: DeserializeOwned>(stream: &mut impl Read) -> Result<Option<T>> {
+    let mut len = [0; 4];
+    match stream.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    let len = u32::from_be_bytes(len);
+    if len > MAX_MESSAGE_LEN {
+        return Err(eyre!("Message of {len} bytes is too large"));
+    }
+
+    let mut body = Zeroizing::new(vec![0; len as usize]);
+    stream.read_exact(&mut body)?;
+
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// A connection to a running `bwrsd`.
+pub struct AgentClient {
+    stream: UnixStream,
+}
+impl AgentClient {
+    /// Connect to the agent, returning `None` if it isn't running.
+    pub fn connect() -> Result<Option<Self>> {
+        match UnixStream::connect(socket_path()?) {
+            Ok(stream) => Ok(Some(Self { stream })),
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => {
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Start `bwrsd` in the background and connect to it once it's listening. It's given the
+    /// timeouts and sync interval from the global config, so keys handed to it still expire.
+    ///
+    /// `bwrsd` is looked for next to the current executable, then on `$PATH`.
+    pub fn start() -> Result<Self> {
+        let sibling = std::env::current_exe()
+            .ok()
+            .map(|exe| exe.with_file_name("bwrsd"))
+            .filter(|path| path.is_file());
+        let program = sibling.unwrap_or_else(|| PathBuf::from("bwrsd"));
+
+        // With --daemonize, this process exits as soon as the agent has detached.
+        let status = Command::new(&program)
+            .arg("--daemonize")
+            .args(Storage::open()?.config()?.agent_args())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .status()
+            .wrap_err_with(|| format!("Failed to start {}", program.display()))?;
+        if !status.success() {
+            return Err(eyre!("{} exited with {status}", program.display()));
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(client) = Self::connect()? {
+                return Ok(client);
+            }
+            if Instant::now() > deadline {
+                return Err(eyre!("Timed out waiting for bwrsd to start"));
+            }
+            thread::sleep(Duration::from_millis(25));
+        }
+    }
+
+    pub fn request(&mut self, request: &Request) -> Result<Response> {
+        write_message(&mut self.stream, request)?;
+
+        match read_message(&mut self.stream)? {
+            Some(Response::Error { message }) => Err(eyre!("bwrsd: {message}")),
+            Some(response) => Ok(response),
+            None => Err(eyre!("bwrsd closed the connection")),
+        }
+    }
+}
@@ -1,18 +1,120 @@
+use std::collections::HashMap;
+
 use dialoguer::{theme::ColorfulTheme, Input};
 use eyre::{eyre, Result};
-use reqwest::{blocking::Client, StatusCode};
-use serde::{Deserialize, Deserializer, Serialize};
+use reqwest::{
+    blocking::{multipart, Client, Response},
+    Method, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use uuid::Uuid;
 
 use crate::crypto::Base64;
 
+pub const DEFAULT_BASE_URL: &str = "https://api.bitwarden.com";
+pub const DEFAULT_IDENTITY_URL: &str = "https://identity.bitwarden.com";
+pub const DEFAULT_NOTIFICATIONS_URL: &str = "https://notifications.bitwarden.com";
+
+/// The feature flag under which clients give each item its own key.
+pub const FEATURE_CIPHER_KEY_ENCRYPTION: &str = "enableCipherKeyEncryption";
+
+/// Which server an account talks to. `None` means the official Bitwarden cloud.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct ServerConfig {
+    pub url: Option<String>,
+}
+impl ServerConfig {
+    #[must_use]
+    pub fn base_url(&self) -> String {
+        match &self.url {
+            Some(url) => format!("{}/api", url.trim_end_matches('/')),
+            None => DEFAULT_BASE_URL.to_string(),
+        }
+    }
+    #[must_use]
+    pub fn identity_url(&self) -> String {
+        match &self.url {
+            Some(url) => format!("{}/identity", url.trim_end_matches('/')),
+            None => DEFAULT_IDENTITY_URL.to_string(),
+        }
+    }
+    #[must_use]
+    pub fn notifications_url(&self) -> String {
+        match &self.url {
+            Some(url) => format!("{}/notifications", url.trim_end_matches('/')),
+            None => DEFAULT_NOTIFICATIONS_URL.to_string(),
+        }
+    }
+}
+
+/// What the server reports about itself from `/config`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerFeatures {
+    pub version: Option<String>,
+    #[serde(default)]
+    pub feature_states: HashMap<String, serde_json::Value>,
+}
+impl ServerFeatures {
+    /// Whether a feature flag is turned on. Flags the server doesn't know about are off.
+    #[must_use]
+    pub fn enabled(&self, flag: &str) -> bool {
+        self.feature_states
+            .get(flag)
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or(false)
+    }
+}
+
+/// Where to upload a new attachment's contents, once its metadata has been added to the item.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentUpload {
+    pub attachment_id: String,
+    pub url: String,
+    pub file_upload_type: FileUploadType,
+    /// The updated item, still encrypted.
+    pub cipher_response: Option<serde_json::Value>,
+    pub cipher_mini_response: Option<serde_json::Value>,
+}
+
+/// An attachment's contents under a new key of its own, for moving an item with attachments
+/// from before attachments had their own keys into an organization.
+#[derive(Clone)]
+pub struct SharedAttachment {
+    /// The file name, encrypted with the organization's key.
+    pub file_name: String,
+    /// The new key, encrypted with the organization's key.
+    pub key: String,
+    /// The file, encrypted with the new key.
+    pub data: Vec<u8>,
+}
+
+#[derive(Deserialize_repr, Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FileUploadType {
+    /// Uploaded to the server itself.
+    Direct = 0,
+    /// Uploaded to a pre-signed Azure blob storage URL.
+    Azure = 1,
+}
+
+/// The body of a failed request, explaining what was wrong with it.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorResponse {
+    message: Option<String>,
+    #[serde(default)]
+    validation_errors: Option<HashMap<String, Vec<String>>>,
+}
+
 #[derive(Serialize)]
 struct PreloginRequest {
     email: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct KdfConfig {
     #[serde(rename = "kdf")]
     pub kind: KdfKind,
@@ -24,13 +126,94 @@ pub struct KdfConfig {
     pub parallelism: Option<u32>,
 }
 
-#[derive(Deserialize_repr, Debug)]
+#[derive(Serialize_repr, Deserialize_repr, Clone, Copy, Debug)]
 #[repr(u8)]
 pub enum KdfKind {
     Pbkdf2 = 0,
     Argon2id = 1,
 }
 
+#[derive(Serialize_repr, Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PolicyKind {
+    TwoFactorAuthentication = 0,
+    MasterPassword = 1,
+    PasswordGenerator = 2,
+    SingleOrg = 3,
+    RequireSso = 4,
+    PersonalOwnership = 5,
+    DisableSend = 6,
+    SendOptions = 7,
+    ResetPassword = 8,
+    MaximumVaultTimeout = 9,
+    DisablePersonalVaultExport = 10,
+    ActivateAutofill = 11,
+    Unknown = u8::MAX,
+}
+impl From<u8> for PolicyKind {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => Self::TwoFactorAuthentication,
+            1 => Self::MasterPassword,
+            2 => Self::PasswordGenerator,
+            3 => Self::SingleOrg,
+            4 => Self::RequireSso,
+            5 => Self::PersonalOwnership,
+            6 => Self::DisableSend,
+            7 => Self::SendOptions,
+            8 => Self::ResetPassword,
+            9 => Self::MaximumVaultTimeout,
+            10 => Self::DisablePersonalVaultExport,
+            11 => Self::ActivateAutofill,
+            _ => Self::Unknown,
+        }
+    }
+}
+impl<'de> Deserialize<'de> for PolicyKind {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        u8::deserialize(d).map(Self::from)
+    }
+}
+
+/// An organization policy, as returned by sync.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Policy {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    #[serde(rename = "type")]
+    pub kind: PolicyKind,
+    pub data: Option<serde_json::Value>,
+    pub enabled: bool,
+}
+
+/// A member of an organization, as seen by its admins. `id` identifies the membership, and
+/// `user_id` the member's account once they have accepted the invitation.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationUser {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub email: Option<String>,
+    pub status: OrganizationUserStatus,
+}
+
+#[derive(Deserialize_repr, Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i8)]
+pub enum OrganizationUserStatus {
+    Revoked = -1,
+    Invited = 0,
+    /// Waiting for an admin to confirm them by sharing the organization's key.
+    Accepted = 1,
+    Confirmed = 2,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicKeyResponse {
+    public_key: String,
+}
+
 #[derive(Serialize, Debug)]
 pub struct ConnectRequest {
     scope: String,
@@ -68,6 +251,7 @@ pub enum ClientKind {
 #[repr(u8)]
 pub enum DeviceKind {
     Android = 0,
+    #[allow(non_camel_case_types)]
     iOS = 1,
     ChromeExtension = 2,
     FirefoxExtension = 3,
@@ -107,18 +291,93 @@ pub struct ConnectDevice {
     // #[serde(rename = "devicePushToken")]
     // push_token: String
 }
+impl ConnectDevice {
+    fn current(id: Uuid) -> Self {
+        Self {
+            #[cfg(target_os = "windows")]
+            kind: DeviceKind::WindowsCLI,
+            #[cfg(target_os = "macos")]
+            kind: DeviceKind::MacOsCLI,
+            #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+            kind: DeviceKind::LinuxCLI,
+            id,
+            name: env!("CARGO_PKG_NAME").to_string(),
+        }
+    }
+}
 #[derive(Serialize, Debug)]
 #[serde(untagged)]
 pub enum ConnectAuth {
     Password { username: String, password: String },
+    RefreshToken { refresh_token: String },
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Session {
-    access_token: String,
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_in: u64,
+    /// The user key, encrypted with the stretched master key.
+    #[serde(rename = "Key")]
+    pub key: Option<String>,
+    /// The user's private key, encrypted with the user key.
+    #[serde(rename = "PrivateKey")]
+    pub private_key: Option<String>,
+    /// Returned when the user asked to remember their two-factor login on this device.
+    #[serde(rename = "TwoFactorToken")]
+    pub two_factor_token: Option<String>,
+}
+impl Session {
+    /// Read the user's id from the `sub` claim of the access token.
+    pub fn user_id(&self) -> Result<Uuid> {
+        access_token_user_id(&self.access_token)
+    }
 }
 
-#[derive(Serialize_repr, Debug)]
+/// Read the user's id from the `sub` claim of an access token.
+pub fn access_token_user_id(access_token: &str) -> Result<Uuid> {
+    #[derive(Deserialize)]
+    struct Claims {
+        sub: Uuid,
+    }
+
+    let payload = access_token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| eyre!("Malformed access token"))?;
+    let claims: Claims = serde_json::from_slice(&Base64::decode_url_safe(payload)?)?;
+
+    Ok(claims.sub)
+}
+
+/// Returned when the server rejects an access token, which usually means it has expired.
+#[derive(Debug)]
+pub struct Unauthorized;
+impl std::fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("The server rejected the access token")
+    }
+}
+impl std::error::Error for Unauthorized {}
+
+/// Returned by [`ApiClient::get`] for a 404, so callers can tell a deleted object from a failure.
+#[derive(Debug)]
+struct NotFound;
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("The server could not find the object")
+    }
+}
+impl std::error::Error for NotFound {}
+
+#[derive(Serialize)]
+struct RevocationRequest<'a> {
+    token: &'a str,
+    token_type_hint: &'a str,
+    client_id: ClientKind,
+}
+
+#[derive(Serialize_repr, Clone, Copy, Debug)]
 #[repr(u8)]
 pub enum TwoFactorProvider {
     Authenticator,
@@ -209,6 +468,7 @@ impl TwoFactorProvider {
         }
     }
 
+    #[allow(unused_variables)]
     pub async fn prepare_provider(&self, client: ApiClient) -> Result<()> {
         match *self {
             Self::Authenticator => {}
@@ -274,6 +534,12 @@ pub struct TwoFactorVerification {
     pub token: String,
     #[serde(rename = "twoFactorProvider")]
     pub provider: TwoFactorProvider,
+    #[serde(rename = "twoFactorRemember", serialize_with = "serialize_flag")]
+    pub remember: bool,
+}
+
+fn serialize_flag<S: serde::Serializer>(flag: &bool, s: S) -> std::result::Result<S::Ok, S::Error> {
+    s.serialize_u8(u8::from(*flag))
 }
 
 #[derive(Debug)]
@@ -302,6 +568,18 @@ impl ApiClient {
                 .unwrap(),
         }
     }
+    #[must_use]
+    pub fn from_config(server: &ServerConfig) -> Self {
+        Self::new(server.base_url(), server.identity_url())
+    }
+    #[must_use]
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+    #[must_use]
+    pub fn identity_url(&self) -> &str {
+        &self.identity_url
+    }
     pub fn prelogin(&self, email: &str) -> Result<KdfConfig> {
         let config: KdfConfig = self
             .agent
@@ -318,6 +596,7 @@ impl ApiClient {
         &self,
         email: &str,
         password_hash: [u8; 32],
+        device_id: Uuid,
         two_factor: Option<TwoFactorVerification>,
     ) -> Result<LoginResult> {
         let res = self
@@ -328,16 +607,7 @@ impl ApiClient {
                 scope: "api offline_access".to_string(),
                 grant_type: GrantKind::Password,
                 client_id: ClientKind::Cli,
-                device: Some(ConnectDevice {
-                    #[cfg(target_os = "windows")]
-                    kind: DeviceKind::WindowsCLI,
-                    #[cfg(target_os = "macos")]
-                    kind: DeviceKind::MacOsCLI,
-                    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
-                    kind: DeviceKind::LinuxCLI,
-                    id: Uuid::new_v4(),
-                    name: env!("CARGO_PKG_NAME").to_string(),
-                }),
+                device: Some(ConnectDevice::current(device_id)),
                 auth: ConnectAuth::Password {
                     username: email.to_string(),
                     password: Base64::encode(password_hash),
@@ -366,4 +636,501 @@ impl ApiClient {
             _ => todo!(),
         }
     }
+    /// Revoke a refresh token, so it can no longer be used to obtain access tokens.
+    pub fn revoke_token(&self, refresh_token: &str) -> Result<()> {
+        let res = self
+            .agent
+            .post(format!("{}{}", self.identity_url, "/connect/revocation"))
+            .form(&RevocationRequest {
+                token: refresh_token,
+                token_type_hint: "refresh_token",
+                client_id: ClientKind::Cli,
+            })
+            .send()?;
+
+        match res.status() {
+            StatusCode::OK => Ok(()),
+            status => Err(eyre!("Failed to revoke token: {status}")),
+        }
+    }
+    /// Exchange a refresh token for a new access token.
+    pub fn refresh(&self, refresh_token: &str) -> Result<Session> {
+        let res = self
+            .agent
+            .post(format!("{}{}", self.identity_url, "/connect/token"))
+            .form(&ConnectRequest {
+                scope: "api offline_access".to_string(),
+                grant_type: GrantKind::RefreshToken,
+                client_id: ClientKind::Cli,
+                device: None,
+                auth: ConnectAuth::RefreshToken {
+                    refresh_token: refresh_token.to_string(),
+                },
+                two_factor: None,
+            })
+            .send()?;
+
+        match res.status() {
+            StatusCode::OK => Ok(res.json()?),
+            status => Err(eyre!("Failed to refresh the session: {status}")),
+        }
+    }
+
+    /// Fetch the server's version and feature flags. This doesn't need an access token.
+    pub fn config(&self) -> Result<ServerFeatures> {
+        let res = self
+            .agent
+            .get(format!("{}{}", self.base_url, "/config"))
+            .send()?;
+
+        match res.status() {
+            status if status.is_success() => Ok(res.json()?),
+            status => Err(eyre!("GET /config failed: {status}")),
+        }
+    }
+
+    /// Fetch the whole vault, still encrypted, as returned by `/sync`.
+    pub fn sync(&self, access_token: &str) -> Result<serde_json::Value> {
+        self.get(access_token, "/sync?excludeDomains=true")
+    }
+    /// Fetch a single cipher, still encrypted, or `None` if it no longer exists.
+    pub fn cipher(&self, access_token: &str, id: Uuid) -> Result<Option<serde_json::Value>> {
+        self.get_optional(access_token, &format!("/ciphers/{id}/details"))
+    }
+    /// Fetch a single folder, still encrypted, or `None` if it no longer exists.
+    pub fn folder(&self, access_token: &str, id: Uuid) -> Result<Option<serde_json::Value>> {
+        self.get_optional(access_token, &format!("/folders/{id}"))
+    }
+
+    /// Find where to download an attachment from. Servers which don't support this return
+    /// `None`, in which case the attachment's own `url` should be used.
+    pub fn attachment_url(
+        &self,
+        access_token: &str,
+        cipher_id: Uuid,
+        attachment_id: &str,
+    ) -> Result<Option<String>> {
+        #[derive(Deserialize)]
+        struct Attachment {
+            url: String,
+        }
+
+        Ok(self
+            .get_optional::<Attachment>(
+                access_token,
+                &format!("/ciphers/{cipher_id}/attachment/{attachment_id}"),
+            )?
+            .map(|attachment| attachment.url))
+    }
+    /// Download a file, such as an attachment, from a pre-signed URL.
+    pub fn download(&self, url: &str) -> Result<Vec<u8>> {
+        let res = self.agent.get(url).send()?;
+
+        match res.status() {
+            status if status.is_success() => Ok(res.bytes()?.to_vec()),
+            status => Err(eyre!("Download failed: {status}")),
+        }
+    }
+
+    /// Add an item to the personal vault, or to an organization's collections. `cipher` is an
+    /// encrypted cipher request. Returns the new item, still encrypted.
+    pub fn create_cipher(
+        &self,
+        access_token: &str,
+        cipher: &serde_json::Value,
+        collection_ids: &[Uuid],
+    ) -> Result<serde_json::Value> {
+        let res = if cipher["organizationId"].is_null() {
+            self.send(Method::POST, access_token, "/ciphers", cipher)?
+        } else {
+            self.send(
+                Method::POST,
+                access_token,
+                "/ciphers/create",
+                &serde_json::json!({
+                    "cipher": cipher,
+                    "collectionIds": collection_ids,
+                }),
+            )?
+        };
+
+        Ok(res.json()?)
+    }
+    /// Replace an item. Returns the updated item, still encrypted.
+    pub fn update_cipher(
+        &self,
+        access_token: &str,
+        id: Uuid,
+        cipher: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        Ok(self
+            .send(Method::PUT, access_token, &format!("/ciphers/{id}"), cipher)?
+            .json()?)
+    }
+    /// Move an item to the trash.
+    pub fn soft_delete_cipher(&self, access_token: &str, id: Uuid) -> Result<()> {
+        self.send_empty(Method::PUT, access_token, &format!("/ciphers/{id}/delete"))?;
+
+        Ok(())
+    }
+    /// Delete an item for good, whether or not it's in the trash.
+    pub fn delete_cipher(&self, access_token: &str, id: Uuid) -> Result<()> {
+        self.send_empty(Method::DELETE, access_token, &format!("/ciphers/{id}"))?;
+
+        Ok(())
+    }
+    /// Take an item back out of the trash.
+    pub fn restore_cipher(&self, access_token: &str, id: Uuid) -> Result<()> {
+        self.send_empty(Method::PUT, access_token, &format!("/ciphers/{id}/restore"))?;
+
+        Ok(())
+    }
+    /// Set which of its organization's collections an item is in.
+    pub fn update_cipher_collections(
+        &self,
+        access_token: &str,
+        id: Uuid,
+        collection_ids: &[Uuid],
+    ) -> Result<()> {
+        self.send(
+            Method::PUT,
+            access_token,
+            &format!("/ciphers/{id}/collections"),
+            &serde_json::json!({ "collectionIds": collection_ids }),
+        )?;
+
+        Ok(())
+    }
+    /// Move an item into an organization's collections. `cipher` must already be encrypted
+    /// with the organization's key. Returns the moved item, still encrypted.
+    pub fn share_cipher(
+        &self,
+        access_token: &str,
+        id: Uuid,
+        cipher: &serde_json::Value,
+        collection_ids: &[Uuid],
+    ) -> Result<serde_json::Value> {
+        Ok(self
+            .send(
+                Method::PUT,
+                access_token,
+                &format!("/ciphers/{id}/share"),
+                &serde_json::json!({
+                    "cipher": cipher,
+                    "collectionIds": collection_ids,
+                }),
+            )?
+            .json()?)
+    }
+    /// Replace the contents of an attachment from before attachments had their own keys, ahead
+    /// of moving its item into an organization.
+    pub fn share_attachment(
+        &self,
+        access_token: &str,
+        cipher_id: Uuid,
+        attachment_id: &str,
+        organization_id: Uuid,
+        attachment: SharedAttachment,
+    ) -> Result<()> {
+        let path = format!(
+            "/ciphers/{cipher_id}/attachment/{attachment_id}/share?organizationId={organization_id}"
+        );
+        let res = self
+            .agent
+            .post(format!("{}{}", self.base_url, path))
+            .bearer_auth(access_token)
+            .multipart(multipart::Form::new().text("key", attachment.key).part(
+                "data",
+                multipart::Part::bytes(attachment.data).file_name(attachment.file_name),
+            ))
+            .send()?;
+
+        Self::check(&Method::POST, &path, res)?;
+
+        Ok(())
+    }
+
+    /// Add an attachment's metadata to an item, returning where to upload its contents.
+    /// `file_name` and `key` are encrypted, and `file_size` is the size of the encrypted file.
+    pub fn create_attachment(
+        &self,
+        access_token: &str,
+        cipher_id: Uuid,
+        file_name: &str,
+        key: &str,
+        file_size: usize,
+    ) -> Result<AttachmentUpload> {
+        Ok(self
+            .send(
+                Method::POST,
+                access_token,
+                &format!("/ciphers/{cipher_id}/attachment/v2"),
+                &serde_json::json!({
+                    "key": key,
+                    "fileName": file_name,
+                    "fileSize": file_size,
+                    "adminRequest": false,
+                }),
+            )?
+            .json()?)
+    }
+    /// Upload an attachment's encrypted contents, as directed by [`ApiClient::create_attachment`].
+    pub fn upload_attachment(
+        &self,
+        access_token: &str,
+        cipher_id: Uuid,
+        upload: &AttachmentUpload,
+        file_name: &str,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        match upload.file_upload_type {
+            FileUploadType::Direct => {
+                let path = format!("/ciphers/{cipher_id}/attachment/{}", upload.attachment_id);
+                let res = self
+                    .agent
+                    .post(format!("{}{path}", self.base_url))
+                    .bearer_auth(access_token)
+                    .multipart(multipart::Form::new().part(
+                        "data",
+                        multipart::Part::bytes(data).file_name(file_name.to_string()),
+                    ))
+                    .send()?;
+                Self::check(&Method::POST, &path, res)?;
+
+                Ok(())
+            }
+            // The upload URL is signed, so the access token plays no part.
+            FileUploadType::Azure => {
+                let res = self
+                    .agent
+                    .put(&upload.url)
+                    .header("x-ms-version", "2020-04-08")
+                    .header("x-ms-blob-type", "BlockBlob")
+                    .body(data)
+                    .send()?;
+
+                match res.status() {
+                    status if status.is_success() => Ok(()),
+                    status => Err(eyre!("Upload failed: {status}")),
+                }
+            }
+        }
+    }
+
+    /// Remove an attachment from an item.
+    pub fn delete_attachment(
+        &self,
+        access_token: &str,
+        cipher_id: Uuid,
+        attachment_id: &str,
+    ) -> Result<()> {
+        self.send_empty(
+            Method::DELETE,
+            access_token,
+            &format!("/ciphers/{cipher_id}/attachment/{attachment_id}"),
+        )?;
+
+        Ok(())
+    }
+
+    /// Add a folder. `name` is encrypted. Returns the new folder, still encrypted.
+    pub fn create_folder(&self, access_token: &str, name: &str) -> Result<serde_json::Value> {
+        Ok(self
+            .send(
+                Method::POST,
+                access_token,
+                "/folders",
+                &serde_json::json!({ "name": name }),
+            )?
+            .json()?)
+    }
+    /// Rename a folder. `name` is encrypted. Returns the updated folder, still encrypted.
+    pub fn update_folder(
+        &self,
+        access_token: &str,
+        id: Uuid,
+        name: &str,
+    ) -> Result<serde_json::Value> {
+        Ok(self
+            .send(
+                Method::PUT,
+                access_token,
+                &format!("/folders/{id}"),
+                &serde_json::json!({ "name": name }),
+            )?
+            .json()?)
+    }
+
+    /// Delete a folder. The server moves its items out of it.
+    pub fn delete_folder(&self, access_token: &str, id: Uuid) -> Result<()> {
+        self.send_empty(Method::DELETE, access_token, &format!("/folders/{id}"))?;
+
+        Ok(())
+    }
+
+    /// Add a collection to an organization. `collection` is an encrypted collection request.
+    /// Returns the new collection, still encrypted.
+    pub fn create_collection(
+        &self,
+        access_token: &str,
+        organization_id: Uuid,
+        collection: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        Ok(self
+            .send(
+                Method::POST,
+                access_token,
+                &format!("/organizations/{organization_id}/collections"),
+                collection,
+            )?
+            .json()?)
+    }
+    /// Replace an organization's collection. Returns the updated collection, still encrypted.
+    pub fn update_collection(
+        &self,
+        access_token: &str,
+        organization_id: Uuid,
+        id: Uuid,
+        collection: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        Ok(self
+            .send(
+                Method::PUT,
+                access_token,
+                &format!("/organizations/{organization_id}/collections/{id}"),
+                collection,
+            )?
+            .json()?)
+    }
+
+    /// Delete an organization's collection. Its items stay in the organization.
+    pub fn delete_collection(
+        &self,
+        access_token: &str,
+        organization_id: Uuid,
+        id: Uuid,
+    ) -> Result<()> {
+        self.send_empty(
+            Method::DELETE,
+            access_token,
+            &format!("/organizations/{organization_id}/collections/{id}"),
+        )?;
+
+        Ok(())
+    }
+
+    /// Fetch a member of an organization, or `None` if there's no such member.
+    pub fn organization_user(
+        &self,
+        access_token: &str,
+        organization_id: Uuid,
+        id: Uuid,
+    ) -> Result<Option<OrganizationUser>> {
+        self.get_optional(
+            access_token,
+            &format!("/organizations/{organization_id}/users/{id}"),
+        )
+    }
+    /// Fetch a user's public key, DER encoded.
+    pub fn user_public_key(&self, access_token: &str, user_id: Uuid) -> Result<Vec<u8>> {
+        let res: PublicKeyResponse =
+            self.get(access_token, &format!("/users/{user_id}/public-key"))?;
+
+        Base64::decode(res.public_key)
+    }
+    /// Confirm a member who has accepted their invitation, giving them the organization's key.
+    /// `key` is the organization's key, encrypted with the member's public key.
+    pub fn confirm_organization_user(
+        &self,
+        access_token: &str,
+        organization_id: Uuid,
+        id: Uuid,
+        key: &str,
+    ) -> Result<()> {
+        self.send(
+            Method::POST,
+            access_token,
+            &format!("/organizations/{organization_id}/users/{id}/confirm"),
+            &serde_json::json!({ "key": key }),
+        )?;
+
+        Ok(())
+    }
+
+    fn get_optional<T: DeserializeOwned>(
+        &self,
+        access_token: &str,
+        path: &str,
+    ) -> Result<Option<T>> {
+        match self.get(access_token, path) {
+            Err(e) if e.downcast_ref::<NotFound>().is_some() => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    fn get<T: DeserializeOwned>(&self, access_token: &str, path: &str) -> Result<T> {
+        let res = self
+            .agent
+            .get(format!("{}{}", self.base_url, path))
+            .bearer_auth(access_token)
+            .send()?;
+
+        match res.status() {
+            status if status.is_success() => Ok(res.json()?),
+            StatusCode::UNAUTHORIZED => Err(Unauthorized.into()),
+            StatusCode::NOT_FOUND => Err(NotFound.into()),
+            status => Err(eyre!("GET {path} failed: {status}")),
+        }
+    }
+
+    /// Send a JSON request, turning a failure into the server's explanation of it.
+    fn send<B: Serialize + ?Sized>(
+        &self,
+        method: Method,
+        access_token: &str,
+        path: &str,
+        body: &B,
+    ) -> Result<Response> {
+        let res = self
+            .agent
+            .request(method.clone(), format!("{}{}", self.base_url, path))
+            .bearer_auth(access_token)
+            .json(body)
+            .send()?;
+
+        Self::check(&method, path, res)
+    }
+    /// Send a request without a body, such as a deletion.
+    fn send_empty(&self, method: Method, access_token: &str, path: &str) -> Result<Response> {
+        let res = self
+            .agent
+            .request(method.clone(), format!("{}{}", self.base_url, path))
+            .bearer_auth(access_token)
+            .send()?;
+
+        Self::check(&method, path, res)
+    }
+    fn check(method: &Method, path: &str, res: Response) -> Result<Response> {
+        match res.status() {
+            status if status.is_success() => Ok(res),
+            StatusCode::UNAUTHORIZED => Err(Unauthorized.into()),
+            StatusCode::NOT_FOUND => Err(NotFound.into()),
+            status => {
+                let error = res.json::<ErrorResponse>().ok();
+                let message = error
+                    .as_ref()
+                    .and_then(|error| error.validation_errors.as_ref())
+                    .and_then(|errors| errors.values().flatten().next())
+                    .or_else(|| error.as_ref().and_then(|error| error.message.as_ref()));
+
+                Err(match message {
+                    Some(message) => eyre!("{message}"),
+                    None if status == StatusCode::FORBIDDEN => {
+                        eyre!("You do not have permission to do this.")
+                    }
+                    None => eyre!("{method} {path} failed: {status}"),
+                })
+            }
+        }
+    }
 }
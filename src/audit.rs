@@ -0,0 +1,31 @@
+use eyre::{eyre, Result};
+use reqwest::blocking::Client;
+use sha1::{Digest, Sha1};
+
+const PWNED_PASSWORDS_URL: &str = "https://api.pwnedpasswords.com/range";
+
+/// Count how many times a password appears in known data breaches, using the Have I Been Pwned
+/// range API. Only the first five characters of the password's SHA-1 hash are sent.
+pub fn password_exposures(password: &str) -> Result<u64> {
+    let hash = Sha1::digest(password.as_bytes())
+        .iter()
+        .fold(String::new(), |hash, b| hash + &format!("{b:02X}"));
+    let (prefix, suffix) = hash.split_at(5);
+
+    let res = Client::new()
+        .get(format!("{PWNED_PASSWORDS_URL}/{prefix}"))
+        // Pad the response with fake entries, so its size doesn't reveal the prefix.
+        .header("Add-Padding", "true")
+        .send()?;
+    if !res.status().is_success() {
+        return Err(eyre!("Failed to check the password: {}", res.status()));
+    }
+
+    Ok(res
+        .text()?
+        .lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .find(|(candidate, _)| candidate.eq_ignore_ascii_case(suffix))
+        .and_then(|(_, count)| count.parse().ok())
+        .unwrap_or(0))
+}
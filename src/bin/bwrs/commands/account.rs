@@ -0,0 +1,68 @@
+use bwrs::storage::{Storage, DEFAULT_PROFILE};
+use clap::{Args, Subcommand};
+
+use super::{lock_agent, CliCommand, Context};
+
+#[derive(Args)]
+pub struct AccountArgs {
+    #[command(subcommand)]
+    action: AccountAction,
+}
+
+#[derive(Subcommand)]
+enum AccountAction {
+    /// List stored accounts, marking the active one.
+    List,
+    /// Use an account by default for future commands.
+    Switch {
+        /// Name of the account to switch to.
+        name: String,
+    },
+    /// Securely remove an account's local data. This does not revoke its tokens.
+    Remove {
+        /// Name of the account to remove.
+        name: String,
+    },
+}
+
+pub struct Account;
+impl CliCommand for Account {
+    type Args = AccountArgs;
+    fn handle(_: &Context, args: Self::Args) -> eyre::Result<()> {
+        let storage = Storage::open()?;
+
+        match args.action {
+            AccountAction::List => {
+                let config = storage.config()?;
+                let active = config.active_account.as_deref().unwrap_or(DEFAULT_PROFILE);
+
+                for name in storage.profiles()? {
+                    let profile = storage.profile(&name)?;
+                    let marker = if name == active { '*' } else { ' ' };
+                    let email = profile
+                        .account()?
+                        .map_or_else(|| "logged out".to_string(), |account| account.email);
+                    let server = profile
+                        .server()?
+                        .url
+                        .unwrap_or_else(|| "bitwarden.com".to_string());
+
+                    println!("{marker} {name}\t{email}\t{server}");
+                }
+            }
+            AccountAction::Switch { name } => {
+                storage.switch(&name)?;
+                println!("Switched to account \"{name}\".");
+            }
+            AccountAction::Remove { name } => {
+                lock_agent(&name)?;
+                for path in storage.remove(&name)? {
+                    println!("Removed {}", path.display());
+                }
+                println!("Removed account \"{name}\".");
+            }
+        }
+
+        Ok(())
+    }
+}
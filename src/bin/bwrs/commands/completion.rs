@@ -4,7 +4,7 @@ use eyre::OptionExt;
 
 use crate::Cli;
 
-use super::CliCommand;
+use super::{CliCommand, Context};
 
 #[derive(Args)]
 pub struct CompletionArgs {
@@ -15,7 +15,7 @@ pub struct CompletionArgs {
 pub struct Completion;
 impl CliCommand for Completion {
     type Args = CompletionArgs;
-    fn handle(args: Self::Args) -> eyre::Result<()> {
+    fn handle(_: &Context, args: Self::Args) -> eyre::Result<()> {
         clap_complete::generate(
             args.shell
                 .or(Shell::from_env())
@@ -0,0 +1,102 @@
+use bwrs::{
+    api::ServerConfig,
+    storage::{GlobalConfig, Storage, DEFAULT_AGENT_IDLE_TIMEOUT, DEFAULT_AGENT_SYNC_INTERVAL},
+};
+use clap::{Args, Subcommand};
+
+use super::{CliCommand, Context};
+
+#[derive(Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    key: ConfigKey,
+}
+
+#[derive(Subcommand)]
+enum ConfigKey {
+    /// Show or set the server used by the current account. Omit the URL to use bitwarden.com.
+    Server { url: Option<String> },
+    /// Show or set whether bwrsd is started automatically to hold unlocked keys.
+    Agent {
+        #[arg(action = clap::ArgAction::Set)]
+        enabled: Option<bool>,
+    },
+    /// Show or set how many seconds bwrsd keeps an account unlocked without its keys being
+    /// used, 0 for no limit. Applies when bwrsd is next started.
+    AgentIdleTimeout { seconds: Option<u64> },
+    /// Show or set how many seconds bwrsd keeps an account unlocked even while it's in use, 0
+    /// for no limit. Applies when bwrsd is next started.
+    AgentAbsoluteTimeout { seconds: Option<u64> },
+    /// Show or set how often bwrsd syncs unlocked accounts in seconds, 0 to turn it off.
+    /// Applies when bwrsd is next started.
+    AgentSyncInterval { seconds: Option<u64> },
+}
+
+pub struct Config;
+impl CliCommand for Config {
+    type Args = ConfigArgs;
+    fn handle(ctx: &Context, args: Self::Args) -> eyre::Result<()> {
+        match args.key {
+            ConfigKey::Server { url } => {
+                let profile = ctx.profile()?;
+                profile.save_server(&ServerConfig { url })?;
+
+                println!("Saved setting `server`.");
+            }
+            ConfigKey::Agent { enabled: None } => {
+                println!("{}", Storage::open()?.config()?.agent.unwrap_or(true));
+            }
+            ConfigKey::Agent { enabled } => {
+                let storage = Storage::open()?;
+                let mut config = storage.config()?;
+                config.agent = enabled;
+                storage.save_config(&config)?;
+
+                println!("Saved setting `agent`.");
+            }
+            ConfigKey::AgentIdleTimeout { seconds } => setting(
+                "agent-idle-timeout",
+                seconds,
+                DEFAULT_AGENT_IDLE_TIMEOUT,
+                |config| &mut config.agent_idle_timeout,
+            )?,
+            ConfigKey::AgentAbsoluteTimeout { seconds } => {
+                setting("agent-absolute-timeout", seconds, 0, |config| {
+                    &mut config.agent_absolute_timeout
+                })?
+            }
+            ConfigKey::AgentSyncInterval { seconds } => setting(
+                "agent-sync-interval",
+                seconds,
+                DEFAULT_AGENT_SYNC_INTERVAL,
+                |config| &mut config.agent_sync_interval,
+            )?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Show a number of seconds from the global config, or its default if unset, or save `value`.
+fn setting(
+    name: &str,
+    value: Option<u64>,
+    default: u64,
+    field: impl FnOnce(&mut GlobalConfig) -> &mut Option<u64>,
+) -> eyre::Result<()> {
+    let storage = Storage::open()?;
+    let mut config = storage.config()?;
+    let setting = field(&mut config);
+
+    match value {
+        None => println!("{}", setting.unwrap_or(default)),
+        Some(value) => {
+            *setting = Some(value);
+            storage.save_config(&config)?;
+
+            println!("Saved setting `{name}`.");
+        }
+    }
+
+    Ok(())
+}
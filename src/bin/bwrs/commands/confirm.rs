@@ -0,0 +1,118 @@
+use std::io::IsTerminal;
+
+use bwrs::{
+    api::OrganizationUserStatus,
+    crypto::{self, PublicKey},
+};
+use clap::{Args, ValueEnum};
+use dialoguer::{theme::ColorfulTheme, Confirm as Prompt};
+use eyre::{eyre, OptionExt, Result};
+use uuid::Uuid;
+
+use super::{CliCommand, Context};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Object {
+    OrgMember,
+}
+
+#[derive(Args)]
+pub struct ConfirmArgs {
+    #[arg(value_enum)]
+    object: Object,
+    /// The member's id in the organization.
+    id: Uuid,
+    /// The organization the member was invited to.
+    #[arg(long)]
+    organizationid: Uuid,
+    /// The member's fingerprint phrase, as they see it, to confirm without being prompted.
+    #[arg(long, value_name = "PHRASE")]
+    fingerprint: Option<String>,
+}
+
+pub struct Confirm;
+impl CliCommand for Confirm {
+    type Args = ConfirmArgs;
+    fn handle(ctx: &Context, args: Self::Args) -> Result<()> {
+        let profile = ctx.profile()?;
+        let account = profile
+            .account()?
+            .ok_or_else(|| eyre!("You are not logged in."))?;
+        let keys = ctx.keys(&profile, &account)?;
+
+        match args.object {
+            Object::OrgMember => {
+                let organization_key = keys.get(Some(args.organizationid))?;
+
+                let (member, public_key) = profile.with_api(|client, access_token| {
+                    let member = client
+                        .organization_user(access_token, args.organizationid, args.id)?
+                        .ok_or_eyre("Member id does not exist for this organization.")?;
+                    match member.status {
+                        OrganizationUserStatus::Accepted => {}
+                        OrganizationUserStatus::Confirmed => {
+                            return Err(eyre!("This member is already confirmed."))
+                        }
+                        _ => {
+                            return Err(eyre!(
+                                "Only members who have accepted their invitation can be confirmed."
+                            ))
+                        }
+                    }
+                    let user_id = member
+                        .user_id
+                        .ok_or_eyre("The member has no account yet.")?;
+
+                    Ok((member, client.user_public_key(access_token, user_id)?))
+                })?;
+                let user_id = member
+                    .user_id
+                    .ok_or_eyre("The member has no account yet.")?;
+
+                // The phrase should be checked with the member over another channel, so that a
+                // key swapped in by the server isn't given the organization's key.
+                let phrase = crypto::fingerprint(&user_id.to_string(), &public_key);
+                eprintln!(
+                    "Fingerprint phrase of {}: {phrase}",
+                    member.email.as_deref().unwrap_or("the member"),
+                );
+                match args.fingerprint.as_deref() {
+                    Some(expected) if expected.trim().eq_ignore_ascii_case(&phrase) => {}
+                    Some(_) => {
+                        return Err(eyre!(
+                            "The fingerprint phrase doesn't match. The member was not confirmed."
+                        ))
+                    }
+                    None if std::io::stdin().is_terminal() => {
+                        let confirmed = Prompt::with_theme(&ColorfulTheme::default())
+                            .with_prompt("Does it match the phrase the member sees?")
+                            .default(false)
+                            .interact()?;
+                        if !confirmed {
+                            return Err(eyre!("The member was not confirmed."));
+                        }
+                    }
+                    None => {
+                        return Err(eyre!(
+                            "Pass the phrase the member sees with --fingerprint to confirm them without a terminal."
+                        ))
+                    }
+                }
+
+                let key = PublicKey::from_der(&public_key)?
+                    .encrypt(organization_key.as_bytes())?
+                    .to_string();
+                profile.with_api(|client, access_token| {
+                    client.confirm_organization_user(
+                        access_token,
+                        args.organizationid,
+                        args.id,
+                        &key,
+                    )
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+
+use bwrs::{crypto::SymmetricKey, storage::Profile, vault::Cipher};
+use clap::{Args, ValueEnum};
+use eyre::{eyre, OptionExt, Result};
+use uuid::Uuid;
+
+use super::{
+    encoded_json,
+    request::{CollectionRequest, FolderRequest},
+    save, synced_vault, view, CliCommand, Context, Keys,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Object {
+    Item,
+    Folder,
+    /// A file attached to an existing item.
+    Attachment,
+    OrgCollection,
+}
+
+#[derive(Args)]
+pub struct CreateArgs {
+    #[arg(value_enum)]
+    object: Object,
+    /// The object as base64 encoded JSON, as produced by `bwrs encode` from a template. Read
+    /// from stdin if not given.
+    encoded_json: Option<String>,
+    /// The file to attach.
+    #[arg(long, required_if_eq("object", "attachment"))]
+    file: Option<PathBuf>,
+    /// The item to attach the file to.
+    #[arg(long, required_if_eq("object", "attachment"))]
+    itemid: Option<Uuid>,
+    /// The organization to create the collection in, which must match the JSON's.
+    #[arg(long, required_if_eq("object", "org-collection"))]
+    organizationid: Option<Uuid>,
+}
+
+pub struct Create;
+impl CliCommand for Create {
+    type Args = CreateArgs;
+    fn handle(ctx: &Context, args: Self::Args) -> Result<()> {
+        let profile = ctx.profile()?;
+        let account = profile
+            .account()?
+            .ok_or_else(|| eyre!("You are not logged in."))?;
+        let keys = ctx.keys(&profile, &account)?;
+
+        let output = match args.object {
+            Object::Item => {
+                let mut cipher = encoded_json::<Cipher>(args.encoded_json)?;
+                cipher.id = None;
+                cipher.attachments.clear();
+
+                let key = keys.get(cipher.organization_id)?;
+                if profile.cipher_key_encryption() {
+                    cipher.generate_key(key)?;
+                }
+
+                let request = cipher.to_request(key)?;
+                let created = profile.with_api(|client, access_token| {
+                    client.create_cipher(access_token, &request, &cipher.collection_ids)
+                })?;
+                save(&profile, "ciphers", &created)?;
+
+                serde_json::to_string(&Cipher::decrypt(&created, key)?)?
+            }
+            Object::Folder => {
+                let name =
+                    encoded_json::<FolderRequest>(args.encoded_json)?.encrypt(&keys.user_key)?;
+
+                let created = profile
+                    .with_api(|client, access_token| client.create_folder(access_token, &name))?;
+                save(&profile, "folders", &created)?;
+
+                serde_json::to_string(&view::folder(&created, &keys)?)?
+            }
+            Object::Attachment => {
+                let path = args.file.ok_or_eyre("--file is required")?;
+                let item_id = args.itemid.ok_or_eyre("--itemid is required")?;
+
+                let created = attach(&profile, &keys, item_id, &path)?;
+                serde_json::to_string(&created)?
+            }
+            Object::OrgCollection => {
+                let collection = encoded_json::<CollectionRequest>(args.encoded_json)?;
+                let organization_id = collection.organization(args.organizationid)?;
+                let request = collection.encrypt(keys.get(Some(organization_id))?);
+
+                let created = profile.with_api(|client, access_token| {
+                    client.create_collection(access_token, organization_id, &request)
+                })?;
+                save(&profile, "collections", &created)?;
+
+                serde_json::to_string(&view::collection(&created, &keys)?)?
+            }
+        };
+
+        println!("{output}");
+
+        Ok(())
+    }
+}
+
+/// Encrypt a file with a new key of its own, and attach it to an item. Returns the updated
+/// item.
+fn attach(profile: &Profile, keys: &Keys, item_id: Uuid, path: &Path) -> Result<Cipher> {
+    let vault = synced_vault(profile)?;
+    let item = view::item(view::find(&vault["ciphers"], item_id)?, keys)?;
+
+    let file_name = path
+        .file_name()
+        .ok_or_eyre("The file has no name")?
+        .to_string_lossy()
+        .into_owned();
+    let contents = zeroize::Zeroizing::new(std::fs::read(path)?);
+
+    let key = keys.get(item.organization_id)?;
+    let item_key = item.item_key(key)?;
+    let cipher_key = item_key.as_ref().unwrap_or(key);
+
+    let attachment_key = SymmetricKey::generate();
+    let data = attachment_key.encrypt(&contents).to_buffer()?;
+    let file_name = cipher_key.encrypt(file_name.as_bytes()).to_string();
+    let wrapped_key = cipher_key.encrypt(attachment_key.as_bytes()).to_string();
+
+    let updated = profile.with_api(|client, access_token| {
+        let upload = client.create_attachment(
+            access_token,
+            item_id,
+            &file_name,
+            &wrapped_key,
+            data.len(),
+        )?;
+
+        // Don't leave an attachment behind without its contents.
+        if let Err(e) =
+            client.upload_attachment(access_token, item_id, &upload, &file_name, data.clone())
+        {
+            client.delete_attachment(access_token, item_id, &upload.attachment_id)?;
+            return Err(e);
+        }
+
+        match upload.cipher_response {
+            Some(cipher) => Ok(Some(cipher)),
+            None => client.cipher(access_token, item_id),
+        }
+    })?;
+    let updated = updated.ok_or_eyre("Not found.")?;
+    save(profile, "ciphers", &updated)?;
+
+    Cipher::decrypt(&updated, key)
+}
@@ -0,0 +1,104 @@
+use clap::{Args, ValueEnum};
+use eyre::{eyre, OptionExt, Result, WrapErr};
+use uuid::Uuid;
+
+use super::{permitted, synced_vault, view, CliCommand, Context};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Object {
+    Item,
+    Folder,
+    Attachment,
+    OrgCollection,
+}
+
+#[derive(Args)]
+pub struct DeleteArgs {
+    #[arg(value_enum)]
+    object: Object,
+    /// The object's id.
+    id: String,
+    /// The item holding the attachment.
+    #[arg(long, required_if_eq("object", "attachment"))]
+    itemid: Option<Uuid>,
+    /// The organization the collection belongs to.
+    #[arg(long, required_if_eq("object", "org-collection"))]
+    organizationid: Option<Uuid>,
+    /// Delete the item for good, instead of moving it to the trash.
+    #[arg(short, long)]
+    permanent: bool,
+}
+
+pub struct Delete;
+impl CliCommand for Delete {
+    type Args = DeleteArgs;
+    fn handle(ctx: &Context, args: Self::Args) -> Result<()> {
+        let profile = ctx.profile()?;
+        profile
+            .account()?
+            .ok_or_else(|| eyre!("You are not logged in."))?;
+        let vault = synced_vault(&profile)?;
+
+        match args.object {
+            Object::Item => {
+                let id = guid(&args.id)?;
+                let cipher = view::find(&vault["ciphers"], id)?;
+                if !permitted(cipher, "delete") {
+                    return Err(eyre!("You do not have permission to delete this item."));
+                }
+
+                let cipher = profile.with_api(|client, access_token| {
+                    if args.permanent {
+                        client.delete_cipher(access_token, id)?;
+                        Ok(None)
+                    } else {
+                        client.soft_delete_cipher(access_token, id)?;
+                        client.cipher(access_token, id)
+                    }
+                })?;
+                profile.upsert("ciphers", id, cipher)?;
+            }
+            Object::Folder => {
+                let id = guid(&args.id)?;
+                view::find(&vault["folders"], id)?;
+
+                profile.with_api(|client, access_token| client.delete_folder(access_token, id))?;
+                profile.remove_folder(id)?;
+            }
+            Object::Attachment => {
+                let item_id = args.itemid.ok_or_eyre("--itemid is required")?;
+                let cipher = view::find(&vault["ciphers"], item_id)?;
+                view::elements(&cipher["attachments"])
+                    .find(|attachment| attachment["id"].as_str() == Some(&args.id))
+                    .ok_or_else(|| eyre!("Attachment `{}` was not found.", args.id))?;
+                if !permitted(cipher, "edit") {
+                    return Err(eyre!("You do not have permission to edit this item."));
+                }
+
+                let cipher = profile.with_api(|client, access_token| {
+                    client.delete_attachment(access_token, item_id, &args.id)?;
+                    client.cipher(access_token, item_id)
+                })?;
+                profile.upsert("ciphers", item_id, cipher)?;
+            }
+            Object::OrgCollection => {
+                let id = guid(&args.id)?;
+                let organization_id = args
+                    .organizationid
+                    .ok_or_eyre("--organizationid is required")?;
+
+                profile.with_api(|client, access_token| {
+                    client.delete_collection(access_token, organization_id, id)
+                })?;
+                profile.remove_collection(id)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn guid(id: &str) -> Result<Uuid> {
+    id.parse()
+        .wrap_err("`id` argument is invalid. It must be a GUID.")
+}
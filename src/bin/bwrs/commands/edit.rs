@@ -0,0 +1,123 @@
+use bwrs::vault::Cipher;
+use clap::{Args, ValueEnum};
+use eyre::{eyre, OptionExt, Result};
+use uuid::Uuid;
+
+use super::{
+    encoded_json,
+    request::{CollectionRequest, FolderRequest},
+    save, synced_vault, view, CliCommand, Context,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Object {
+    Item,
+    Folder,
+    /// Which of its organization's collections an item is in, as a list of ids.
+    ItemCollections,
+    OrgCollection,
+}
+
+#[derive(Args)]
+pub struct EditArgs {
+    #[arg(value_enum)]
+    object: Object,
+    /// The object's id.
+    id: Uuid,
+    /// The edited object as base64 encoded JSON, as produced by `bwrs encode`. Read from stdin
+    /// if not given.
+    encoded_json: Option<String>,
+    /// The organization the collection belongs to, which must match the JSON's.
+    #[arg(long, required_if_eq("object", "org-collection"))]
+    organizationid: Option<Uuid>,
+}
+
+pub struct Edit;
+impl CliCommand for Edit {
+    type Args = EditArgs;
+    fn handle(ctx: &Context, args: Self::Args) -> Result<()> {
+        let profile = ctx.profile()?;
+        let account = profile
+            .account()?
+            .ok_or_else(|| eyre!("You are not logged in."))?;
+        let keys = ctx.keys(&profile, &account)?;
+        let vault = synced_vault(&profile)?;
+
+        let output = match args.object {
+            Object::Item => {
+                let original = view::item(view::find(&vault["ciphers"], args.id)?, &keys)?;
+                let mut cipher = encoded_json::<Cipher>(args.encoded_json)?;
+
+                // Moving between organizations and collections, and managing attachments, have
+                // their own commands. The JSON also leaves out keys, so those are kept.
+                cipher.id = original.id;
+                cipher.organization_id = original.organization_id;
+                cipher.collection_ids = original.collection_ids.clone();
+                cipher.key = original.key.clone();
+                cipher.attachments = original.attachments.clone();
+                cipher.revision_date = original.revision_date.clone();
+                cipher.update_password_history(&original);
+
+                let key = keys.get(cipher.organization_id)?;
+                if profile.cipher_key_encryption() {
+                    cipher.generate_key(key)?;
+                }
+
+                let request = cipher.to_request(key)?;
+                let updated = profile.with_api(|client, access_token| {
+                    client.update_cipher(access_token, args.id, &request)
+                })?;
+                save(&profile, "ciphers", &updated)?;
+
+                serde_json::to_string(&Cipher::decrypt(&updated, key)?)?
+            }
+            Object::Folder => {
+                view::find(&vault["folders"], args.id)?;
+                let name =
+                    encoded_json::<FolderRequest>(args.encoded_json)?.encrypt(&keys.user_key)?;
+
+                let updated = profile.with_api(|client, access_token| {
+                    client.update_folder(access_token, args.id, &name)
+                })?;
+                save(&profile, "folders", &updated)?;
+
+                serde_json::to_string(&view::folder(&updated, &keys)?)?
+            }
+            Object::ItemCollections => {
+                let item = view::item(view::find(&vault["ciphers"], args.id)?, &keys)?;
+                if item.organization_id.is_none() {
+                    return Err(eyre!(
+                        "Only items in an organization can be in collections. Move the item \
+                         to an organization first."
+                    ));
+                }
+                let collection_ids = encoded_json::<Vec<Uuid>>(args.encoded_json)?;
+
+                let updated = profile.with_api(|client, access_token| {
+                    client.update_cipher_collections(access_token, args.id, &collection_ids)?;
+                    client.cipher(access_token, args.id)
+                })?;
+                let updated = updated.ok_or_eyre("Not found.")?;
+                save(&profile, "ciphers", &updated)?;
+
+                serde_json::to_string(&view::item(&updated, &keys)?)?
+            }
+            Object::OrgCollection => {
+                let collection = encoded_json::<CollectionRequest>(args.encoded_json)?;
+                let organization_id = collection.organization(args.organizationid)?;
+                let request = collection.encrypt(keys.get(Some(organization_id))?);
+
+                let updated = profile.with_api(|client, access_token| {
+                    client.update_collection(access_token, organization_id, args.id, &request)
+                })?;
+                save(&profile, "collections", &updated)?;
+
+                serde_json::to_string(&view::collection(&updated, &keys)?)?
+            }
+        };
+
+        println!("{output}");
+
+        Ok(())
+    }
+}
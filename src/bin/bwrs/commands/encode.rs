@@ -5,7 +5,7 @@ use eyre::eyre;
 
 use bwrs::crypto::Base64;
 
-use super::CliCommand;
+use super::{CliCommand, Context};
 
 #[derive(Args)]
 pub struct EncodeArgs {}
@@ -13,7 +13,7 @@ pub struct EncodeArgs {}
 pub struct Encode;
 impl CliCommand for Encode {
     type Args = EncodeArgs;
-    fn handle(_: Self::Args) -> eyre::Result<()> {
+    fn handle(_: &Context, _: Self::Args) -> eyre::Result<()> {
         let mut stdin = std::io::stdin();
         if stdin.is_terminal() {
             return Err(eyre!("No stdin was piped in."));
@@ -0,0 +1,238 @@
+use bwrs::{
+    forwarder::{
+        AddyIo, DuckDuckGo, Fastmail, FirefoxRelay, ForwardEmail, ForwardedEmailProvider,
+        SimpleLogin,
+    },
+    generator::{
+        self, GeneratorPolicy, GeneratorType, PassphraseOptions, PasswordOptions, UsernameOptions,
+    },
+};
+use clap::{Args, ValueEnum};
+use eyre::Result;
+
+use super::{CliCommand, Context};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum UsernameType {
+    /// A random word.
+    Word,
+    /// A plus-addressed email, e.g. `user+x7kq2mfa@example.com`.
+    Subaddress,
+    /// An address at a catch-all domain, e.g. `x7kq2mfa@example.com`.
+    Catchall,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Forwarder {
+    #[value(name = "simplelogin")]
+    SimpleLogin,
+    #[value(name = "addyio", alias = "anonaddy")]
+    AddyIo,
+    #[value(name = "firefoxrelay")]
+    FirefoxRelay,
+    Fastmail,
+    #[value(name = "duckduckgo")]
+    DuckDuckGo,
+    #[value(name = "forwardemail")]
+    ForwardEmail,
+}
+
+#[derive(Args)]
+pub struct GenerateArgs {
+    /// Include uppercase characters. Without any of -u, -l, -n or -s, -uln is used.
+    #[arg(short, long)]
+    uppercase: bool,
+    /// Include lowercase characters.
+    #[arg(short, long)]
+    lowercase: bool,
+    /// Include numbers.
+    #[arg(short, long)]
+    number: bool,
+    /// Include special characters.
+    #[arg(short, long)]
+    special: bool,
+    /// Length of the password, from 5 to 128.
+    #[arg(long, default_value_t = 14)]
+    length: usize,
+    /// Minimum number of numbers.
+    #[arg(long = "minNumber", default_value_t = 1)]
+    min_number: usize,
+    /// Minimum number of special characters.
+    #[arg(long = "minSpecial", default_value_t = 1)]
+    min_special: usize,
+    /// Avoid ambiguous characters, such as `l`, `I` and `1`.
+    #[arg(long)]
+    ambiguous: bool,
+
+    /// Generate a passphrase instead of a password.
+    #[arg(short, long)]
+    passphrase: bool,
+    /// Number of words in the passphrase, from 3 to 20.
+    #[arg(long, default_value_t = 3)]
+    words: usize,
+    /// Word separator, or `space` or `empty`. Only its first character is used.
+    #[arg(long, default_value = "-")]
+    separator: String,
+    /// Capitalize each word of the passphrase, or the username.
+    #[arg(short, long)]
+    capitalize: bool,
+    /// Append a number to one of the passphrase's words, or to the username.
+    #[arg(long = "includeNumber")]
+    include_number: bool,
+
+    /// Generate a username instead of a password.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "TYPE",
+        num_args = 0..=1,
+        default_missing_value = "word",
+        conflicts_with = "passphrase"
+    )]
+    username: Option<UsernameType>,
+    /// Email address to plus-address.
+    #[arg(long, required_if_eq("username", "subaddress"))]
+    email: Option<String>,
+    /// Catch-all domain, or the forwarding service's domain to make the alias at.
+    #[arg(
+        long,
+        required_if_eq_any([("username", "catchall"), ("forwarder", "addyio"), ("forwarder", "forwardemail")])
+    )]
+    domain: Option<String>,
+    /// Use the website's name in the email address, instead of random characters. Forwarding
+    /// services note it on the alias.
+    #[arg(long)]
+    website: Option<String>,
+    /// Make an alias with a forwarding service, instead of any other type of username.
+    #[arg(long, value_enum, requires_all = ["username", "forwarder_token"])]
+    forwarder: Option<Forwarder>,
+    /// The forwarding service's API token.
+    #[arg(
+        long = "forwarderToken",
+        env = "BWRS_FORWARDER_TOKEN",
+        hide_env_values = true
+    )]
+    forwarder_token: Option<String>,
+    /// The forwarding service's URL, for a self-hosted SimpleLogin or addy.io.
+    #[arg(long = "forwarderUrl")]
+    forwarder_url: Option<String>,
+}
+
+pub struct Generate;
+impl CliCommand for Generate {
+    type Args = GenerateArgs;
+    fn handle(ctx: &Context, args: Self::Args) -> Result<()> {
+        if let Some(forwarder) = args.forwarder {
+            let token = args.forwarder_token.unwrap_or_default();
+            let domain = args.domain.unwrap_or_default();
+            let url = |default: &str| args.forwarder_url.clone().unwrap_or(default.to_string());
+            let provider: Box<dyn ForwardedEmailProvider> = match forwarder {
+                Forwarder::SimpleLogin => Box::new(SimpleLogin {
+                    base_url: url(SimpleLogin::DEFAULT_URL),
+                    api_key: token,
+                }),
+                Forwarder::AddyIo => Box::new(AddyIo {
+                    base_url: url(AddyIo::DEFAULT_URL),
+                    api_token: token,
+                    domain,
+                }),
+                Forwarder::FirefoxRelay => Box::new(FirefoxRelay {
+                    base_url: url(FirefoxRelay::DEFAULT_URL),
+                    api_token: token,
+                }),
+                Forwarder::Fastmail => Box::new(Fastmail {
+                    base_url: url(Fastmail::DEFAULT_URL),
+                    api_token: token,
+                }),
+                Forwarder::DuckDuckGo => Box::new(DuckDuckGo {
+                    base_url: url(DuckDuckGo::DEFAULT_URL),
+                    api_token: token,
+                }),
+                Forwarder::ForwardEmail => Box::new(ForwardEmail {
+                    base_url: url(ForwardEmail::DEFAULT_URL),
+                    api_token: token,
+                    domain,
+                }),
+            };
+            println!("{}", provider.generate(args.website.as_deref())?);
+
+            return Ok(());
+        }
+        if let Some(username) = args.username {
+            let options = match username {
+                UsernameType::Word => UsernameOptions::Word {
+                    capitalize: args.capitalize,
+                    include_number: args.include_number,
+                },
+                UsernameType::Subaddress => UsernameOptions::Subaddress {
+                    email: args.email.unwrap_or_default(),
+                    website: args.website,
+                },
+                UsernameType::Catchall => UsernameOptions::Catchall {
+                    domain: args.domain.unwrap_or_default(),
+                    website: args.website,
+                },
+            };
+            println!("{}", generator::username(&options)?);
+
+            return Ok(());
+        }
+
+        // Organizations can require stronger passwords than asked for.
+        let policy =
+            GeneratorPolicy::from_policies(&ctx.profile()?.policies()?)?.unwrap_or_default();
+        let mut changes = Vec::new();
+        let passphrase = match policy.required_type {
+            Some(GeneratorType::Passphrase) if !args.passphrase => {
+                changes.push("A passphrase is required.".to_string());
+                true
+            }
+            Some(GeneratorType::Password) if args.passphrase => {
+                changes.push("A password is required.".to_string());
+                false
+            }
+            _ => args.passphrase,
+        };
+
+        let generated = if passphrase {
+            let separator = match args.separator.as_str() {
+                "space" => " ".to_string(),
+                "empty" => String::new(),
+                separator => separator.chars().take(1).collect(),
+            };
+
+            let mut options = PassphraseOptions {
+                words: args.words,
+                separator,
+                capitalize: args.capitalize,
+                include_number: args.include_number,
+            };
+            changes.extend(policy.enforce_passphrase(&mut options));
+
+            generator::passphrase(&options)
+        } else {
+            let defaults = !(args.uppercase || args.lowercase || args.number || args.special);
+
+            let mut options = PasswordOptions {
+                length: args.length,
+                uppercase: args.uppercase || defaults,
+                lowercase: args.lowercase || defaults,
+                numbers: args.number || defaults,
+                special: args.special,
+                min_numbers: args.min_number,
+                min_special: args.min_special,
+                avoid_ambiguous: args.ambiguous,
+            };
+            changes.extend(policy.enforce_password(&mut options));
+
+            generator::password(&options)?
+        };
+
+        for change in changes {
+            eprintln!("Organization policy: {change}");
+        }
+        println!("{generated}");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+use std::{io::Write, path::PathBuf};
+
+use bwrs::{
+    audit, crypto,
+    storage::{Account, Profile},
+    totp::Totp,
+    vault::{Card, Cipher, Field, Identity, Login, LoginUri, SecureNote},
+};
+use clap::{Args, ValueEnum};
+use eyre::{eyre, OptionExt, Result, WrapErr};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::{
+    download_attachment,
+    request::{CollectionRequest, FolderRequest},
+    synced_vault,
+    view::{self, contains, elements},
+    CliCommand, Context, Keys,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Object {
+    Item,
+    Username,
+    Password,
+    Uri,
+    Totp,
+    Notes,
+    /// How many times the item's password appears in known data breaches.
+    Exposed,
+    Attachment,
+    Folder,
+    Collection,
+    Organization,
+    /// The fingerprint phrase of a user's public key, by user id, or of the user's own as `me`.
+    Fingerprint,
+    /// A JSON template for `bwrs create` and `bwrs edit`.
+    Template,
+}
+
+#[derive(Args)]
+pub struct GetArgs {
+    #[arg(value_enum)]
+    object: Object,
+    /// The object's id, or a search term which matches exactly one object. Attachments may also
+    /// be found by file name, and templates are named, e.g. `item.login`.
+    id: String,
+    /// The item holding the attachment, by id or search term.
+    #[arg(long, required_if_eq("object", "attachment"))]
+    itemid: Option<String>,
+    /// Save the attachment to this file, or into this directory, instead of writing it to stdout.
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+pub struct Get;
+impl CliCommand for Get {
+    type Args = GetArgs;
+    fn handle(ctx: &Context, args: Self::Args) -> Result<()> {
+        if args.object == Object::Template {
+            let template =
+                template(&args.id)?.ok_or_else(|| eyre!("Unknown template object: {}", args.id))?;
+            println!("{}", serde_json::to_string(&template)?);
+
+            return Ok(());
+        }
+
+        let profile = ctx.profile()?;
+        let account = profile
+            .account()?
+            .ok_or_else(|| eyre!("You are not logged in."))?;
+        if args.object == Object::Fingerprint {
+            println!("{}", fingerprint(ctx, &profile, &account, &args.id)?);
+
+            return Ok(());
+        }
+
+        let keys = ctx.keys(&profile, &account)?;
+        let vault = synced_vault(&profile)?;
+
+        // A detail of a login, such as its username.
+        let login_detail = |detail: fn(&Login) -> Option<&str>, missing: &str| -> Result<String> {
+            let has_detail = |item: &Cipher| item.login().and_then(detail).is_some_and(has_text);
+            let item = find_item(&vault, &keys, &args.id, Some(&has_detail))?;
+            let login = item.login().ok_or_eyre("Not a login.")?;
+
+            text(detail(login), missing)
+        };
+
+        let output = match args.object {
+            Object::Item => serde_json::to_string(&find_item(&vault, &keys, &args.id, None)?)?,
+            Object::Username => login_detail(
+                |login| login.username.as_deref(),
+                "No username available for this login.",
+            )?,
+            Object::Password => login_detail(
+                |login| login.password.as_deref(),
+                "No password available for this login.",
+            )?,
+            Object::Uri => login_detail(
+                |login| login.uris.first().and_then(|uri| uri.uri.as_deref()),
+                "No uri available for this login.",
+            )?,
+            Object::Totp => login_detail(
+                |login| login.totp.as_deref(),
+                "No TOTP available for this login.",
+            )?
+            .parse::<Totp>()?
+            .now()?,
+            Object::Notes => {
+                let has_notes = |item: &Cipher| item.notes.as_deref().is_some_and(has_text);
+                let item = find_item(&vault, &keys, &args.id, Some(&has_notes))?;
+                text(item.notes.as_deref(), "No notes available for this item.")?
+            }
+            Object::Exposed => {
+                let password = login_detail(
+                    |login| login.password.as_deref(),
+                    "No password available for this login.",
+                )?;
+                audit::password_exposures(&password)?.to_string()
+            }
+            Object::Attachment => {
+                let item_id = args.itemid.as_deref().ok_or_eyre("--itemid is required")?;
+                let item = find_item(&vault, &keys, item_id, None)?;
+                let query = args.id.to_lowercase();
+
+                let attachment = pick(
+                    item.attachments
+                        .iter()
+                        .filter(|attachment| {
+                            attachment.id == query
+                                || attachment
+                                    .file_name
+                                    .as_deref()
+                                    .is_some_and(|name| contains(name, &query))
+                        })
+                        .collect(),
+                    |attachment| {
+                        format!(
+                            "{} ({})",
+                            attachment.id,
+                            attachment.file_name.as_deref().unwrap_or_default()
+                        )
+                    },
+                )?;
+
+                let data = download_attachment(&profile, &keys, &item, attachment)?;
+                match args.output {
+                    Some(mut path) => {
+                        if path.is_dir() || path.as_os_str().to_string_lossy().ends_with('/') {
+                            path.push(attachment.file_name.as_deref().unwrap_or(&attachment.id));
+                        }
+                        std::fs::write(&path, &data)?;
+                        eprintln!("Saved {}", path.display());
+                    }
+                    None => std::io::stdout().write_all(&data)?,
+                }
+
+                return Ok(());
+            }
+            Object::Folder => {
+                let folders = elements(&vault["folders"])
+                    .filter_map(|folder| view::folder(folder, &keys).ok())
+                    .collect();
+                serde_json::to_string(&find_named(folders, &args.id)?)?
+            }
+            Object::Collection => {
+                let collections = elements(&vault["collections"])
+                    .filter_map(|collection| view::collection(collection, &keys).ok())
+                    .collect();
+                serde_json::to_string(&find_named(collections, &args.id)?)?
+            }
+            Object::Organization => {
+                let organizations = elements(&vault["profile"]["organizations"])
+                    .map(view::organization)
+                    .collect();
+                serde_json::to_string(&find_named(organizations, &args.id)?)?
+            }
+            Object::Fingerprint | Object::Template => unreachable!("handled before unlocking"),
+        };
+
+        println!("{output}");
+
+        Ok(())
+    }
+}
+
+fn has_text(value: &str) -> bool {
+    !value.trim().is_empty()
+}
+fn text(value: Option<&str>, missing: &str) -> Result<String> {
+    value
+        .filter(|value| has_text(value))
+        .map(str::to_string)
+        .ok_or_else(|| eyre!("{missing}"))
+}
+
+/// Resolve the single candidate, or explain why there isn't one.
+fn pick<T>(mut candidates: Vec<T>, describe: impl Fn(&T) -> String) -> Result<T> {
+    match candidates.len() {
+        0 => Err(eyre!("Not found.")),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(eyre!(
+            "More than one result was found. Try getting a specific object by `id` instead. \
+             The following objects were found:\n{}",
+            candidates
+                .iter()
+                .map(describe)
+                .collect::<Vec<_>>()
+                .join("\n")
+        )),
+    }
+}
+
+/// Find an item by id, or by a search term. If a search matches several items, `filter`
+/// narrows them down to those which have the requested detail.
+fn find_item(
+    vault: &Value,
+    keys: &Keys,
+    query: &str,
+    filter: Option<&dyn Fn(&Cipher) -> bool>,
+) -> Result<Cipher> {
+    if let Ok(id) = query.parse::<Uuid>() {
+        return view::item(view::find(&vault["ciphers"], id)?, keys);
+    }
+
+    let search = query.trim().to_lowercase();
+    let mut items = elements(&vault["ciphers"])
+        .filter_map(|cipher| view::item(cipher, keys).ok())
+        .filter(|item| !item.is_deleted() && view::matches_search(item, &search))
+        .collect::<Vec<_>>();
+    if let Some(filter) = filter.filter(|_| items.len() > 1) {
+        items.retain(|item| filter(item));
+    }
+
+    pick(items, |item| {
+        format!("{} ({})", item.id.unwrap_or_default(), item.name)
+    })
+}
+
+/// Find a folder, collection or organization by id, or by a search of the names.
+fn find_named(objects: Vec<Value>, query: &str) -> Result<Value> {
+    let objects = match query.parse::<Uuid>() {
+        Ok(id) => objects
+            .into_iter()
+            .filter(|object| view::id(&object["id"]).ok().flatten() == Some(id))
+            .collect(),
+        Err(_) => {
+            let search = query.trim().to_lowercase();
+            objects
+                .into_iter()
+                .filter(|object| {
+                    object["name"]
+                        .as_str()
+                        .is_some_and(|name| contains(name, &search))
+                })
+                .collect()
+        }
+    };
+
+    pick(objects, describe)
+}
+
+fn describe(object: &Value) -> String {
+    format!(
+        "{} ({})",
+        object["id"].as_str().unwrap_or_default(),
+        object["name"].as_str().unwrap_or_default()
+    )
+}
+
+/// The fingerprint phrase of the user's own public key, for `me`, or of another user's.
+fn fingerprint(ctx: &Context, profile: &Profile, account: &Account, id: &str) -> Result<String> {
+    let (user_id, public_key) = if id == "me" {
+        let user_key = ctx.user_key(profile, account)?;
+        let private_key = account
+            .private_key(&user_key)?
+            .ok_or_eyre("The account has no key pair.")?;
+
+        (account.user_id, private_key.public_key().to_der()?)
+    } else {
+        let user_id = id
+            .parse::<Uuid>()
+            .wrap_err("`id` argument is invalid. It must be a user id or `me`.")?;
+        let public_key = profile
+            .with_api(|client, access_token| client.user_public_key(access_token, user_id))?;
+
+        (user_id, public_key)
+    };
+
+    Ok(crypto::fingerprint(&user_id.to_string(), &public_key))
+}
+
+/// The templates accepted by `bwrs create` and `bwrs edit`, in the official CLI's shapes.
+fn template(name: &str) -> Result<Option<Value>> {
+    Ok(Some(match name {
+        "item" => {
+            // Items are filled in with one of the type templates, e.g. `item.login`.
+            let mut item = without(serde_json::to_value(Cipher::template())?, &["object", "id"]);
+            item["passwordHistory"] = json!([]);
+            item["collectionIds"] = Value::Null;
+            item["fields"] = json!([]);
+            for kind in ["login", "secureNote", "card", "identity", "sshKey"] {
+                item[kind] = Value::Null;
+            }
+            item
+        }
+        "item.field" => without(serde_json::to_value(Field::template())?, &["linkedId"]),
+        "item.login" => without(
+            serde_json::to_value(Login::template())?,
+            &["passwordRevisionDate"],
+        ),
+        "item.login.uri" => serde_json::to_value(LoginUri::template())?,
+        "item.card" => serde_json::to_value(Card::template())?,
+        "item.identity" => serde_json::to_value(Identity::template())?,
+        "item.securenote" => serde_json::to_value(SecureNote::default())?,
+        "folder" => serde_json::to_value(FolderRequest::template())?,
+        "collection" => serde_json::to_value(CollectionRequest::template())?,
+        "item-collections" => json!(["collection-id1", "collection-id2"]),
+        "org-collection" => serde_json::to_value(CollectionRequest::org_template())?,
+        _ => return Ok(None),
+    }))
+}
+
+fn without(mut object: Value, keys: &[&str]) -> Value {
+    if let Some(map) = object.as_object_mut() {
+        for key in keys {
+            map.remove(*key);
+        }
+    }
+
+    object
+}
@@ -0,0 +1,259 @@
+use std::str::FromStr;
+
+use bwrs::vault::{Cipher, UriMatch};
+use clap::{Args, ValueEnum};
+use eyre::{eyre, Result};
+use regex::RegexBuilder;
+use serde_json::Value;
+use url::{Host, Url};
+use uuid::Uuid;
+
+use super::{
+    synced_vault,
+    view::{self, contains, elements},
+    CliCommand, Context, Keys,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Object {
+    Items,
+    Folders,
+    Collections,
+    Organizations,
+}
+
+/// Which objects to keep, by the id of their folder, collection or organization.
+#[derive(Clone)]
+enum Filter {
+    /// Objects without one.
+    Null,
+    /// Objects with any one.
+    NotNull,
+    Id(Uuid),
+}
+impl FromStr for Filter {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "null" => Ok(Self::Null),
+            "notnull" => Ok(Self::NotNull),
+            id => Ok(Self::Id(id.parse()?)),
+        }
+    }
+}
+impl Filter {
+    fn matches(&self, id: Option<Uuid>) -> bool {
+        match self {
+            Self::Null => id.is_none(),
+            Self::NotNull => id.is_some(),
+            Self::Id(expected) => id == Some(*expected),
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ListArgs {
+    #[arg(value_enum)]
+    object: Object,
+    /// Only list objects whose name contains this. Items are also matched by their username,
+    /// URIs, card or identity details, and id prefix.
+    #[arg(long)]
+    search: Option<String>,
+    /// Only list logins which would be filled on this URL.
+    #[arg(long)]
+    url: Option<String>,
+    /// Only list items in this folder, `null` for none, or `notnull` for any.
+    #[arg(long)]
+    folderid: Option<Filter>,
+    /// Only list items in this collection, `null` for none, or `notnull` for any.
+    #[arg(long)]
+    collectionid: Option<Filter>,
+    /// Only list items or collections in this organization, `null` for none, or `notnull` for
+    /// any.
+    #[arg(long)]
+    organizationid: Option<Filter>,
+    /// List items in the trash, instead of the rest of the vault.
+    #[arg(long)]
+    trash: bool,
+}
+
+pub struct List;
+impl CliCommand for List {
+    type Args = ListArgs;
+    fn handle(ctx: &Context, args: Self::Args) -> Result<()> {
+        let profile = ctx.profile()?;
+        let account = profile
+            .account()?
+            .ok_or_else(|| eyre!("You are not logged in."))?;
+        let keys = ctx.keys(&profile, &account)?;
+        let vault = synced_vault(&profile)?;
+
+        let search = args
+            .search
+            .as_deref()
+            .map(|search| search.trim().to_lowercase())
+            .filter(|search| !search.is_empty());
+
+        let mut objects = match args.object {
+            Object::Items => {
+                let items = items(&vault, &keys, &args, search.as_deref());
+                println!("{}", serde_json::to_string(&items)?);
+
+                return Ok(());
+            }
+            Object::Folders => {
+                let mut folders = decrypt_all(&vault["folders"], |f| view::folder(f, &keys));
+                sort_by_name(&mut folders, |folder| folder["name"].as_str());
+                folders.push(view::no_folder());
+                folders
+            }
+            Object::Collections => {
+                let mut collections = elements(&vault["collections"])
+                    .filter(|c| {
+                        args.organizationid.as_ref().map_or(true, |filter| {
+                            filter.matches(view::id(&c["organizationId"]).ok().flatten())
+                        })
+                    })
+                    .filter_map(|c| warn(view::collection(c, &keys)))
+                    .collect::<Vec<_>>();
+                sort_by_name(&mut collections, |collection| collection["name"].as_str());
+                collections
+            }
+            Object::Organizations => {
+                let mut organizations = elements(&vault["profile"]["organizations"])
+                    .map(view::organization)
+                    .collect::<Vec<_>>();
+                sort_by_name(&mut organizations, |organization| {
+                    organization["name"].as_str()
+                });
+                organizations
+            }
+        };
+        if let Some(search) = &search {
+            objects.retain(|object| {
+                object["name"]
+                    .as_str()
+                    .is_some_and(|name| contains(name, search))
+            });
+        }
+
+        println!("{}", serde_json::to_string(&objects)?);
+
+        Ok(())
+    }
+}
+
+/// Decrypt the items matching the filters, which follow the official CLI: the folder,
+/// collection and organization filters are alternatives, any of which can match.
+fn items(vault: &Value, keys: &Keys, args: &ListArgs, search: Option<&str>) -> Vec<Cipher> {
+    let mut items = decrypt_all(&vault["ciphers"], |c| view::item(c, keys));
+
+    if let Some(url) = &args.url {
+        items.retain(|item| !item.is_deleted() && matches_url(item, url));
+    }
+
+    if args.folderid.is_some() || args.collectionid.is_some() || args.organizationid.is_some() {
+        items.retain(|item| {
+            if args.trash != item.is_deleted() {
+                return false;
+            }
+
+            let in_collection = |filter: &Filter| match filter {
+                Filter::Null => item.collection_ids.is_empty(),
+                Filter::NotNull => !item.collection_ids.is_empty(),
+                Filter::Id(id) => item.collection_ids.contains(id),
+            };
+
+            args.folderid
+                .as_ref()
+                .is_some_and(|filter| filter.matches(item.folder_id))
+                || args
+                    .organizationid
+                    .as_ref()
+                    .is_some_and(|filter| filter.matches(item.organization_id))
+                || args.collectionid.as_ref().is_some_and(in_collection)
+        });
+    } else if search.is_none() {
+        items.retain(|item| args.trash == item.is_deleted());
+    }
+
+    if let Some(search) = search {
+        items.retain(|item| args.trash == item.is_deleted() && view::matches_search(item, search));
+    }
+
+    sort_by_name(&mut items, |item| Some(item.name.as_str()));
+    items
+}
+
+/// Decrypt every object in a list, skipping (with a warning) any which can't be decrypted.
+fn decrypt_all<T>(list: &Value, decrypt: impl Fn(&Value) -> Result<T>) -> Vec<T> {
+    elements(list)
+        .filter_map(|object| warn(decrypt(object)))
+        .collect()
+}
+fn warn<T>(result: Result<T>) -> Option<T> {
+    result.map_err(|e| eprintln!("Warning: {e:#}")).ok()
+}
+
+fn sort_by_name<T>(objects: &mut [T], name: impl Fn(&T) -> Option<&str>) {
+    objects.sort_by_cached_key(|object| name(object).map(str::to_lowercase).unwrap_or_default());
+}
+
+/// Whether a login has a URI which matches `url`, using each URI's match detection. URIs
+/// without one use base domain matching.
+fn matches_url(item: &Cipher, url: &str) -> bool {
+    let Some(login) = item.login() else {
+        return false;
+    };
+
+    login.uris.iter().any(|login_uri| {
+        let Some(uri) = login_uri.uri.as_deref() else {
+            return false;
+        };
+
+        match login_uri.r#match.unwrap_or(UriMatch::Domain) {
+            UriMatch::Domain => domain(url).is_some_and(|domain| self::domain(uri) == Some(domain)),
+            UriMatch::Host => host(url).is_some_and(|host| self::host(uri) == Some(host)),
+            UriMatch::StartsWith => url.starts_with(uri),
+            UriMatch::Exact => url == uri,
+            UriMatch::RegularExpression => RegexBuilder::new(uri)
+                .case_insensitive(true)
+                .build()
+                .is_ok_and(|regex| regex.is_match(url)),
+            UriMatch::Never => false,
+        }
+    })
+}
+
+/// Parse a URI the way it's entered in the vault, where the scheme is often left out.
+fn parse_url(uri: &str) -> Option<Url> {
+    let uri = uri.trim();
+    if uri.contains("://") {
+        Url::parse(uri).ok()
+    } else if uri.contains('.') {
+        Url::parse(&format!("http://{uri}")).ok()
+    } else {
+        None
+    }
+}
+
+/// The registrable domain of a URI, e.g. `example.co.uk` for `https://www.example.co.uk`.
+/// IP addresses and `localhost` are their own domain.
+fn domain(uri: &str) -> Option<String> {
+    match parse_url(uri)?.host()? {
+        Host::Domain(host) if host != "localhost" => psl::domain_str(host).map(str::to_string),
+        host => Some(host.to_string()),
+    }
+}
+
+/// The host of a URI, including the port if it isn't the scheme's default.
+fn host(uri: &str) -> Option<String> {
+    let url = parse_url(uri)?;
+    let host = url.host_str()?;
+
+    Some(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    })
+}
@@ -0,0 +1,28 @@
+use clap::Args;
+use eyre::eyre;
+
+use super::{lock_agent, CliCommand, Context};
+
+#[derive(Args)]
+pub struct LockArgs {}
+
+pub struct Lock;
+impl CliCommand for Lock {
+    type Args = LockArgs;
+    fn handle(ctx: &Context, _: Self::Args) -> eyre::Result<()> {
+        let profile = ctx.profile()?;
+        let mut account = profile
+            .account()?
+            .ok_or_else(|| eyre!("You are not logged in."))?;
+
+        // Forgetting the wrapped key invalidates any exported `BW_SESSION`.
+        account.protected_key = None;
+        profile.save_account(&account)?;
+
+        lock_agent(profile.name())?;
+
+        println!("Your vault is locked.");
+
+        Ok(())
+    }
+}
@@ -1,16 +1,13 @@
-use bwrs::api::{
-    ApiClient, KdfConfig, KdfKind, LoginResult, TwoFactorProvider, TwoFactorVerification,
+use bwrs::{
+    api::{ApiClient, LoginResult, ServerConfig, TwoFactorProvider, TwoFactorVerification},
+    crypto::{EncString, HashPurpose, MasterKey, SymmetricKey},
+    storage::{Account, Storage},
 };
 
-use super::CliCommand;
-use argon2::{
-    Algorithm as Argon2Algorithm, Argon2, Params as Argon2Params, Version as Argon2Version,
-};
+use super::{CliCommand, Context};
 use clap::{Args, ValueEnum};
-use dialoguer::{theme::ColorfulTheme, Input, Password, Select};
-use hkdf::Hkdf;
-use pbkdf2::pbkdf2_hmac_array;
-use sha2::{Digest, Sha256};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, Password, Select};
+use eyre::OptionExt;
 use zeroize::Zeroize;
 
 #[derive(ValueEnum, Clone, Copy)]
@@ -22,6 +19,9 @@ enum TwoStepMethod {
 
 #[derive(Args)]
 pub struct LoginArgs {
+    /// URL of a self-hosted server, remembered for this account.
+    #[arg(long)]
+    server: Option<String>,
     // method: TwoStepMethod,
     // code: String,
     // sso: bool,
@@ -34,11 +34,25 @@ pub struct Login;
 impl CliCommand for Login {
     type Args = LoginArgs;
 
-    fn handle(args: Self::Args) -> eyre::Result<()> {
-        let client = ApiClient::new(
-            "https://api.bitwarden.com".to_string(),
-            "https://identity.bitwarden.com".to_string(),
-        );
+    fn handle(ctx: &Context, args: Self::Args) -> eyre::Result<()> {
+        let storage = Storage::open()?;
+        let profile = storage.select(ctx.account.as_deref())?;
+        if profile.account()?.is_some() {
+            return Err(eyre::eyre!(
+                "Account \"{}\" is already logged in.",
+                profile.name()
+            ));
+        }
+
+        let server = match args.server {
+            Some(url) => {
+                let server = ServerConfig { url: Some(url) };
+                profile.save_server(&server)?;
+                server
+            }
+            None => profile.server()?,
+        };
+        let client = ApiClient::from_config(&server);
         let email: String = Input::with_theme(&ColorfulTheme::default())
             .with_prompt("Email address")
             .validate_with(|input: &String| {
@@ -50,6 +64,7 @@ impl CliCommand for Login {
             })
             .interact_text()
             .unwrap();
+        let email = email.trim().to_lowercase();
 
         let kdf_config = client.prelogin(&email)?;
 
@@ -65,50 +80,23 @@ impl CliCommand for Login {
             .interact()
             .unwrap();
 
-        let mut enc_key = match kdf_config.kind {
-            KdfKind::Pbkdf2 => pbkdf2_hmac_array::<Sha256, 32>(
-                password.as_bytes(),
-                email.as_bytes(),
-                kdf_config.iterations,
-            ),
-            KdfKind::Argon2id => {
-                let salt = Sha256::digest(email.as_bytes());
-                let mut buf = [0; 32];
-
-                Argon2::new(
-                    Argon2Algorithm::Argon2id,
-                    Argon2Version::V0x13,
-                    Argon2Params::new(
-                        kdf_config.memory.unwrap() * 1024,
-                        kdf_config.iterations,
-                        kdf_config.parallelism.unwrap(),
-                        Some(32),
-                    )
-                    .unwrap(),
-                )
-                .hash_password_into(password.as_bytes(), &salt, &mut buf)
-                .unwrap();
-
-                buf
-            }
-        };
-
-        let server_master_key_hash =
-            pbkdf2_hmac_array::<Sha256, 32>(&enc_key, password.as_bytes(), 1);
-        let local_master_key_hash =
-            pbkdf2_hmac_array::<Sha256, 32>(&enc_key, password.as_bytes(), 2);
+        let master_key = MasterKey::derive(&password, &email, &kdf_config)?;
+        let server_master_key_hash = master_key.hash(&password, HashPurpose::ServerAuthorization);
 
         password.zeroize();
 
-        let hkdf = Hkdf::<Sha256>::from_prk(&enc_key).unwrap();
-        hkdf.expand(b"enc", &mut enc_key).unwrap();
+        let device_id = profile.device_id()?;
+        let remembered = profile
+            .two_factor_token(&email)?
+            .map(|token| TwoFactorVerification {
+                token,
+                provider: TwoFactorProvider::Remember,
+                remember: false,
+            });
 
-        let mut mac_key = [0; 32];
-        hkdf.expand(b"mac", &mut mac_key).unwrap();
-
-        let session = match client.login(&email, server_master_key_hash, None)? {
+        let session = match client.login(&email, server_master_key_hash, device_id, remembered)? {
             LoginResult::Success(session) => session,
-            LoginResult::NeedsTwoFactor(providers) => {
+            LoginResult::NeedsTwoFactor(mut providers) => {
                 let provider = Select::with_theme(&ColorfulTheme::default())
                     .with_prompt("Two-step login method")
                     .default(0)
@@ -119,23 +107,65 @@ impl CliCommand for Login {
                             .collect::<Vec<_>>(),
                     )
                     .interact()
-                    .unwrap() as u64;
+                    .unwrap();
 
-                let provider: TwoFactorProvider = provider.try_into().unwrap();
+                let provider = providers.swap_remove(provider);
                 let token = provider.prompt_for_token()?;
+                let remember = Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Remember two-step login on this device?")
+                    .default(false)
+                    .interact()
+                    .unwrap();
 
-                let res = client.login(
+                match client.login(
                     &email,
                     server_master_key_hash,
-                    Some(TwoFactorVerification { token, provider }),
-                )?;
-
-                println!("{res:?}");
-
-                todo!()
+                    device_id,
+                    Some(TwoFactorVerification {
+                        token,
+                        provider,
+                        remember,
+                    }),
+                )? {
+                    LoginResult::Success(session) => session,
+                    LoginResult::NeedsTwoFactor(_) => {
+                        return Err(eyre::eyre!("Two-step login failed"))
+                    }
+                }
             }
         };
-        println!("{session:?}");
+
+        if let Some(token) = &session.two_factor_token {
+            profile.save_two_factor_token(&email, token)?;
+        }
+        let user_key = SymmetricKey::from_slice(
+            &master_key.stretch().decrypt(
+                &session
+                    .key
+                    .as_deref()
+                    .ok_or_eyre("The server did not return a user key")?
+                    .parse::<EncString>()?,
+            )?,
+        )?;
+
+        let mut account = Account {
+            email: email.clone(),
+            user_id: session.user_id()?,
+            kdf: kdf_config,
+            access_token: session.access_token,
+            refresh_token: session.refresh_token,
+            key: session.key,
+            private_key: session.private_key,
+            protected_key: None,
+            last_sync: None,
+        };
+        println!("You are logged in as {email}!");
+        println!();
+        ctx.store_user_key(&profile, &mut account, &user_key)?;
+
+        if storage.config()?.active_account.is_none() {
+            storage.switch(profile.name())?;
+        }
 
         Ok(())
     }
@@ -0,0 +1,36 @@
+use bwrs::api::ApiClient;
+use clap::Args;
+use eyre::eyre;
+
+use super::{lock_agent, CliCommand, Context};
+
+#[derive(Args)]
+pub struct LogoutArgs {}
+
+pub struct Logout;
+impl CliCommand for Logout {
+    type Args = LogoutArgs;
+    fn handle(ctx: &Context, _: Self::Args) -> eyre::Result<()> {
+        let profile = ctx.profile()?;
+        let account = profile
+            .account()?
+            .ok_or_else(|| eyre!("You are not logged in."))?;
+
+        if let Some(refresh_token) = &account.refresh_token {
+            let client = ApiClient::from_config(&profile.server()?);
+            // The local state is removed regardless, so a failed revocation shouldn't block it.
+            if let Err(e) = client.revoke_token(refresh_token) {
+                eprintln!("Warning: {e}");
+            }
+        }
+
+        // The agent would otherwise keep serving the account's secrets until it timed out.
+        lock_agent(profile.name())?;
+        for path in profile.wipe()? {
+            println!("Removed {}", path.display());
+        }
+        println!("You have logged out of {}.", account.email);
+
+        Ok(())
+    }
+}
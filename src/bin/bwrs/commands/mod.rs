@@ -1,15 +1,323 @@
-use eyre::Result;
+use std::{
+    collections::BTreeMap,
+    io::{IsTerminal, Read},
+};
+
+use bwrs::{
+    crypto::{Base64, EncString, SymmetricKey},
+    storage::{self, Profile, Storage},
+    vault::{Attachment, Cipher},
+};
+use eyre::{eyre, OptionExt, Result, WrapErr};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+mod request;
+mod view;
 
 mod login;
 pub use login::Login;
 
+mod logout;
+pub use logout::Logout;
+
 mod encode;
 pub use encode::Encode;
 
+mod lock;
+pub use lock::Lock;
+
+mod unlock;
+pub use unlock::Unlock;
+
+mod sync;
+pub use sync::Sync;
+
+mod generate;
+pub use generate::Generate;
+
+mod config;
+pub use config::Config;
+
+mod status;
+pub use status::Status;
+
+mod account;
+pub use account::Account;
+
 mod completion;
 pub use completion::Completion;
 
+mod list;
+pub use list::List;
+
+mod get;
+pub use get::Get;
+
+mod create;
+pub use create::Create;
+
+mod edit;
+pub use edit::Edit;
+
+mod delete;
+pub use delete::Delete;
+
+mod restore;
+pub use restore::Restore;
+
+mod r#move;
+pub use r#move::Move;
+
+mod confirm;
+pub use confirm::Confirm;
+
+/// State shared by every command, built from the global arguments.
+pub struct Context {
+    pub account: Option<String>,
+    pub no_agent: bool,
+}
+impl Context {
+    /// Open the profile selected with `--account`, or the active one.
+    pub fn profile(&self) -> Result<Profile> {
+        Storage::open()?.select(self.account.as_deref())
+    }
+
+    /// Whether keys may be handed to `bwrsd`, starting it if needed.
+    pub fn agent_enabled(&self) -> Result<bool> {
+        Ok(cfg!(unix) && !self.no_agent && Storage::open()?.config()?.agent.unwrap_or(true))
+    }
+
+    /// Find the user key for an account, from `BW_SESSION` or a running `bwrsd`. A key from
+    /// `BW_SESSION` is handed to `bwrsd` if it doesn't hold one, starting it if needed.
+    pub fn user_key(&self, profile: &Profile, account: &storage::Account) -> Result<SymmetricKey> {
+        self.find_user_key(profile, account, true)
+    }
+    /// Find every key needed to decrypt the vault: the user key, and the keys of the
+    /// organizations the user belongs to.
+    pub fn keys(&self, profile: &Profile, account: &storage::Account) -> Result<Keys> {
+        let user_key = self.user_key(profile, account)?;
+        let org_keys = profile.organization_keys(account, &user_key)?;
+
+        Ok(Keys { user_key, org_keys })
+    }
+    /// Whether the vault can be decrypted, without starting `bwrsd`.
+    pub fn is_unlocked(&self, profile: &Profile, account: &storage::Account) -> bool {
+        self.find_user_key(profile, account, false).is_ok()
+    }
+
+    fn find_user_key(
+        &self,
+        profile: &Profile,
+        account: &storage::Account,
+        start_agent: bool,
+    ) -> Result<SymmetricKey> {
+        if let Ok(session) = std::env::var("BW_SESSION") {
+            let user_key = account.user_key(&SymmetricKey::from_base64(&session)?)?;
+
+            #[cfg(unix)]
+            if start_agent && self.agent_enabled()? {
+                self.hand_to_agent(profile, account, &user_key, false)?;
+            }
+
+            return Ok(user_key);
+        }
+
+        #[cfg(unix)]
+        if self.agent_enabled()? {
+            use bwrs::agent::{AgentClient, Request, Response};
+
+            if let Some(mut agent) = AgentClient::connect()? {
+                if let Response::Keys { user_key, .. } = agent.request(&Request::GetKeys {
+                    account: profile.name().to_string(),
+                })? {
+                    return SymmetricKey::from_base64(&user_key);
+                }
+            }
+        }
+
+        Err(eyre!(
+            "The vault is locked. Run `bwrs unlock` to unlock it."
+        ))
+    }
+
+    /// Give `bwrsd` an account's user key, starting it if it isn't running. Unless `replace` is
+    /// set, a key it already holds is left alone so its timeouts aren't reset.
+    #[cfg(unix)]
+    fn hand_to_agent(
+        &self,
+        profile: &Profile,
+        account: &storage::Account,
+        user_key: &SymmetricKey,
+        replace: bool,
+    ) -> Result<()> {
+        use bwrs::agent::{AgentClient, Request, Response};
+
+        let mut agent = match AgentClient::connect()? {
+            Some(agent) => agent,
+            None => AgentClient::start()?,
+        };
+        if !replace {
+            let held = agent.request(&Request::HasKeys {
+                account: profile.name().to_string(),
+            })?;
+            if let Response::Ok = held {
+                return Ok(());
+            }
+        }
+        agent.request(&Request::Unlock {
+            account: profile.name().to_string(),
+            user_key: zeroize::Zeroizing::new(user_key.to_base64()),
+            org_keys: profile
+                .organization_keys(account, user_key)?
+                .into_iter()
+                .map(|(id, key)| (id, zeroize::Zeroizing::new(key.to_base64())))
+                .collect(),
+        })?;
+
+        Ok(())
+    }
+
+    /// Remember a freshly unlocked user key: wrap it with a new session key, which is printed
+    /// for `BW_SESSION`, and hand it to `bwrsd`.
+    pub fn store_user_key(
+        &self,
+        profile: &Profile,
+        account: &mut storage::Account,
+        user_key: &SymmetricKey,
+    ) -> Result<()> {
+        let session_key = account.protect_user_key(user_key);
+        profile.save_account(account)?;
+
+        #[cfg(unix)]
+        if self.agent_enabled()? {
+            self.hand_to_agent(profile, account, user_key, true)?;
+        }
+
+        println!("To unlock your vault, set your session key to the `BW_SESSION` environment variable. ex:");
+        println!("$ export BW_SESSION=\"{}\"", session_key.to_base64());
+
+        Ok(())
+    }
+}
+
+/// The keys which decrypt an account's vault.
+pub struct Keys {
+    pub user_key: SymmetricKey,
+    pub org_keys: BTreeMap<Uuid, SymmetricKey>,
+}
+impl Keys {
+    /// The key for objects owned by an organization, or by the user if `None`.
+    pub fn get(&self, organization_id: Option<Uuid>) -> Result<&SymmetricKey> {
+        match organization_id {
+            Some(id) => self
+                .org_keys
+                .get(&id)
+                .ok_or_eyre("No key is available for the item's organization"),
+            None => Ok(&self.user_key),
+        }
+    }
+}
+
+/// Make a running `bwrsd` drop an account's keys. Nothing happens if it isn't running.
+fn lock_agent(account: &str) -> Result<()> {
+    #[cfg(unix)]
+    if let Some(mut agent) = bwrs::agent::AgentClient::connect()? {
+        agent.request(&bwrs::agent::Request::Lock {
+            account: Some(account.to_string()),
+        })?;
+    }
+    #[cfg(not(unix))]
+    let _ = account;
+
+    Ok(())
+}
+
+/// Read the vault cache, which must have been fetched with `bwrs sync`.
+fn synced_vault(profile: &Profile) -> Result<serde_json::Value> {
+    profile
+        .vault()?
+        .ok_or_else(|| eyre!("The vault hasn't been synced yet. Run `bwrs sync` first."))
+}
+
+/// Whether the vault cache says the user may `delete`, `restore` or `edit` an item. Items
+/// shared by an organization can be read-only, and older servers only say whether the item can
+/// be edited.
+fn permitted(cipher: &serde_json::Value, permission: &str) -> bool {
+    cipher["permissions"][permission]
+        .as_bool()
+        .or_else(|| cipher["edit"].as_bool())
+        .unwrap_or(true)
+}
+
+/// Download and decrypt an attachment. Its contents are encrypted with its own key if it has
+/// one, which is in turn encrypted with the item's key.
+fn download_attachment(
+    profile: &Profile,
+    keys: &Keys,
+    item: &Cipher,
+    attachment: &Attachment,
+) -> Result<Vec<u8>> {
+    let cipher_id = item.id.ok_or_eyre("The item has no id")?;
+
+    let data = profile.with_api(|client, access_token| {
+        let url = match client.attachment_url(access_token, cipher_id, &attachment.id)? {
+            Some(url) => url,
+            None => attachment
+                .url
+                .clone()
+                .ok_or_eyre("The attachment has no download URL")?,
+        };
+        client.download(&url)
+    })?;
+
+    let key = keys.get(item.organization_id)?;
+    let item_key = item.item_key(key)?;
+    let cipher_key = item_key.as_ref().unwrap_or(key);
+    let data = EncString::from_buffer(&data)?;
+    match &attachment.key {
+        Some(key) => {
+            let key = zeroize::Zeroizing::new(cipher_key.decrypt(&key.parse()?)?);
+            SymmetricKey::from_slice(&key)?.decrypt(&data)
+        }
+        None => cipher_key.decrypt(&data),
+    }
+}
+
+/// Put an object returned by the server into the vault cache, so it can be used before the
+/// next sync.
+fn save(profile: &Profile, list: &str, object: &serde_json::Value) -> Result<()> {
+    let id = view::id(&object["id"])?.ok_or_eyre("The server returned an object without an id")?;
+    profile.upsert(list, id, Some(object.clone()))?;
+
+    Ok(())
+}
+
+/// Parse an object given as base64 encoded JSON, as produced by `bwrs encode`, from an
+/// argument or else from stdin.
+fn encoded_json<T: DeserializeOwned>(encoded: Option<String>) -> Result<T> {
+    let encoded = match encoded {
+        Some(encoded) => encoded,
+        None => {
+            let mut stdin = std::io::stdin();
+            if stdin.is_terminal() {
+                return Err(eyre!(
+                    "No encoded JSON was given, and no stdin was piped in."
+                ));
+            }
+
+            let mut buf = String::new();
+            stdin.read_to_string(&mut buf)?;
+            buf
+        }
+    };
+
+    let json =
+        Base64::decode(encoded.trim()).wrap_err("Error parsing the encoded request data.")?;
+    serde_json::from_slice(&json).wrap_err("Error parsing the encoded request data.")
+}
+
 pub trait CliCommand {
     type Args: clap::Args;
-    fn handle(args: Self::Args) -> Result<()>;
+    fn handle(ctx: &Context, args: Self::Args) -> Result<()>;
 }
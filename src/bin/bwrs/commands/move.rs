@@ -0,0 +1,90 @@
+use bwrs::{api::SharedAttachment, crypto::SymmetricKey, vault::Cipher};
+use clap::Args;
+use eyre::{eyre, Result};
+use uuid::Uuid;
+
+use super::{download_attachment, encoded_json, save, synced_vault, view, CliCommand, Context};
+
+#[derive(Args)]
+pub struct MoveArgs {
+    /// The item to move.
+    itemid: Uuid,
+    /// The organization to move the item to.
+    organizationid: Uuid,
+    /// The ids of the organization's collections to put the item in, as a base64 encoded JSON
+    /// list produced by `bwrs encode`. Read from stdin if not given.
+    encoded_json: Option<String>,
+}
+
+pub struct Move;
+impl CliCommand for Move {
+    type Args = MoveArgs;
+    fn handle(ctx: &Context, args: Self::Args) -> Result<()> {
+        let profile = ctx.profile()?;
+        let account = profile
+            .account()?
+            .ok_or_else(|| eyre!("You are not logged in."))?;
+        let keys = ctx.keys(&profile, &account)?;
+        let vault = synced_vault(&profile)?;
+
+        let mut item = view::item(view::find(&vault["ciphers"], args.itemid)?, &keys)?;
+        if item.organization_id.is_some() {
+            return Err(eyre!("This item already belongs to an organization."));
+        }
+        let organization_key = keys.get(Some(args.organizationid))?;
+        let collection_ids = encoded_json::<Vec<Uuid>>(args.encoded_json)?;
+
+        // Files attached before attachments had their own keys are encrypted with the user
+        // key, so they're uploaded again under new keys of their own.
+        let mut shared = Vec::new();
+        if item.key.is_none() {
+            for attachment in item.attachments.iter().filter(|a| a.key.is_none()) {
+                let contents = zeroize::Zeroizing::new(download_attachment(
+                    &profile, &keys, &item, attachment,
+                )?);
+                let attachment_key = SymmetricKey::generate();
+                let file_name = attachment.file_name.as_deref().unwrap_or_default();
+
+                shared.push((
+                    attachment.id.clone(),
+                    SharedAttachment {
+                        file_name: organization_key.encrypt(file_name.as_bytes()).to_string(),
+                        key: organization_key
+                            .encrypt(attachment_key.as_bytes())
+                            .to_string(),
+                        data: attachment_key.encrypt(&contents).to_buffer()?,
+                    },
+                ));
+            }
+        }
+
+        item.move_to_organization(&keys.user_key, args.organizationid, organization_key)?;
+        for attachment in &mut item.attachments {
+            if let Some((_, upload)) = shared.iter().find(|(id, _)| *id == attachment.id) {
+                attachment.key = Some(upload.key.clone());
+            }
+        }
+        let request = item.to_request(organization_key)?;
+
+        let moved = profile.with_api(|client, access_token| {
+            for (attachment_id, upload) in &shared {
+                client.share_attachment(
+                    access_token,
+                    args.itemid,
+                    attachment_id,
+                    args.organizationid,
+                    upload.clone(),
+                )?;
+            }
+            client.share_cipher(access_token, args.itemid, &request, &collection_ids)
+        })?;
+        save(&profile, "ciphers", &moved)?;
+
+        println!(
+            "{}",
+            serde_json::to_string(&Cipher::decrypt(&moved, organization_key)?)?
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+//! Objects read from the JSON templates accepted by `bwrs create` and `bwrs edit`.
+
+use bwrs::crypto::SymmetricKey;
+use eyre::{eyre, OptionExt, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// A folder, as in the `folder` template.
+#[derive(Serialize, Deserialize)]
+pub struct FolderRequest {
+    pub name: String,
+}
+impl FolderRequest {
+    pub fn template() -> Self {
+        Self {
+            name: "Folder name".to_string(),
+        }
+    }
+
+    pub fn encrypt(&self, key: &SymmetricKey) -> Result<String> {
+        if self.name.trim().is_empty() {
+            return Err(eyre!("A folder needs a name."));
+        }
+
+        Ok(key.encrypt(self.name.as_bytes()).to_string())
+    }
+}
+
+/// An organization collection, as in the `collection` and `org-collection` templates.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionRequest {
+    organization_id: Option<Uuid>,
+    name: String,
+    #[serde(default)]
+    external_id: Option<String>,
+    #[serde(default)]
+    groups: Vec<CollectionAccess>,
+    /// Left out of the `collection` template, which only grants access to groups.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    users: Option<Vec<CollectionAccess>>,
+}
+
+/// A group's or user's access to a collection.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionAccess {
+    id: Uuid,
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    hide_passwords: bool,
+    #[serde(default)]
+    manage: bool,
+}
+impl CollectionAccess {
+    fn template() -> Self {
+        Self {
+            id: Uuid::nil(),
+            read_only: false,
+            hide_passwords: false,
+            manage: false,
+        }
+    }
+}
+
+impl CollectionRequest {
+    pub fn template() -> Self {
+        Self {
+            organization_id: Some(Uuid::nil()),
+            name: "Collection name".to_string(),
+            external_id: None,
+            groups: Vec::new(),
+            users: None,
+        }
+    }
+    pub fn org_template() -> Self {
+        Self {
+            groups: vec![CollectionAccess::template()],
+            users: Some(vec![CollectionAccess::template()]),
+            ..Self::template()
+        }
+    }
+
+    /// The collection's organization, which must be the one given with `--organizationid`.
+    pub fn organization(&self, organization_id: Option<Uuid>) -> Result<Uuid> {
+        let organization_id = organization_id.ok_or_eyre("--organizationid is required")?;
+        if self.organization_id != Some(organization_id) {
+            return Err(eyre!(
+                "--organizationid <organizationid> does not match request object."
+            ));
+        }
+
+        Ok(organization_id)
+    }
+
+    pub fn encrypt(&self, key: &SymmetricKey) -> Value {
+        json!({
+            "name": key.encrypt(self.name.as_bytes()).to_string(),
+            "externalId": self.external_id,
+            "groups": self.groups,
+            "users": self.users.as_deref().unwrap_or_default(),
+        })
+    }
+}
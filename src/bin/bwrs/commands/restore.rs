@@ -0,0 +1,51 @@
+use clap::{Args, ValueEnum};
+use eyre::{eyre, Result};
+use uuid::Uuid;
+
+use super::{permitted, synced_vault, view, CliCommand, Context};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Object {
+    Item,
+}
+
+#[derive(Args)]
+pub struct RestoreArgs {
+    #[arg(value_enum)]
+    object: Object,
+    /// The object's id.
+    id: Uuid,
+}
+
+pub struct Restore;
+impl CliCommand for Restore {
+    type Args = RestoreArgs;
+    fn handle(ctx: &Context, args: Self::Args) -> Result<()> {
+        let profile = ctx.profile()?;
+        profile
+            .account()?
+            .ok_or_else(|| eyre!("You are not logged in."))?;
+        let vault = synced_vault(&profile)?;
+
+        match args.object {
+            Object::Item => {
+                let cipher = view::find(&vault["ciphers"], args.id)?;
+                // Like the official CLI, restoring an item which isn't in the trash does nothing.
+                if cipher["deletedDate"].is_null() {
+                    return Ok(());
+                }
+                if !permitted(cipher, "restore") {
+                    return Err(eyre!("You do not have permission to restore this item."));
+                }
+
+                let cipher = profile.with_api(|client, access_token| {
+                    client.restore_cipher(access_token, args.id)?;
+                    client.cipher(access_token, args.id)
+                })?;
+                profile.upsert("ciphers", args.id, cipher)?;
+            }
+        }
+
+        Ok(())
+    }
+}
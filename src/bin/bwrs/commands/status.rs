@@ -0,0 +1,66 @@
+use clap::Args;
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{CliCommand, Context};
+
+#[derive(Args)]
+pub struct StatusArgs {}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum VaultStatus {
+    Unauthenticated,
+    Locked,
+    Unlocked,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusOutput {
+    server_url: Option<String>,
+    last_sync: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_id: Option<Uuid>,
+    status: VaultStatus,
+}
+
+pub struct Status;
+impl CliCommand for Status {
+    type Args = StatusArgs;
+    fn handle(ctx: &Context, _: Self::Args) -> eyre::Result<()> {
+        let profile = ctx.profile()?;
+        let server_url = profile.server()?.url;
+
+        let output = match profile.account()? {
+            None => StatusOutput {
+                server_url,
+                last_sync: None,
+                user_email: None,
+                user_id: None,
+                status: VaultStatus::Unauthenticated,
+            },
+            Some(account) => {
+                let unlocked = ctx.is_unlocked(&profile, &account);
+
+                StatusOutput {
+                    server_url,
+                    last_sync: account.last_sync,
+                    user_email: Some(account.email),
+                    user_id: Some(account.user_id),
+                    status: if unlocked {
+                        VaultStatus::Unlocked
+                    } else {
+                        VaultStatus::Locked
+                    },
+                }
+            }
+        };
+
+        println!("{}", serde_json::to_string(&output)?);
+
+        Ok(())
+    }
+}
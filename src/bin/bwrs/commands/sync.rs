@@ -0,0 +1,17 @@
+use clap::Args;
+
+use super::{CliCommand, Context};
+
+#[derive(Args)]
+pub struct SyncArgs {}
+
+pub struct Sync;
+impl CliCommand for Sync {
+    type Args = SyncArgs;
+    fn handle(ctx: &Context, _: Self::Args) -> eyre::Result<()> {
+        ctx.profile()?.sync()?;
+        println!("Syncing complete.");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use bwrs::crypto::{EncString, MasterKey, SymmetricKey};
+use clap::Args;
+use dialoguer::{theme::ColorfulTheme, Password};
+use eyre::{eyre, OptionExt};
+use zeroize::Zeroize;
+
+use super::{CliCommand, Context};
+
+#[derive(Args)]
+pub struct UnlockArgs {
+    /// Only check whether the vault is unlocked, exiting with an error if it isn't.
+    #[arg(long)]
+    check: bool,
+}
+
+pub struct Unlock;
+impl CliCommand for Unlock {
+    type Args = UnlockArgs;
+    fn handle(ctx: &Context, args: Self::Args) -> eyre::Result<()> {
+        let profile = ctx.profile()?;
+        let mut account = profile
+            .account()?
+            .ok_or_else(|| eyre!("You are not logged in."))?;
+
+        if args.check {
+            ctx.user_key(&profile, &account)?;
+            println!("Vault is unlocked!");
+
+            return Ok(());
+        }
+
+        let mut password: String = Password::with_theme(&ColorfulTheme::default())
+            .with_prompt("Master password")
+            .interact()
+            .unwrap();
+        let master_key = MasterKey::derive(&password, &account.email, &account.kdf)?;
+        password.zeroize();
+
+        let user_key = master_key
+            .stretch()
+            .decrypt(
+                &account
+                    .key
+                    .as_deref()
+                    .ok_or_eyre("No user key is stored for this account")?
+                    .parse::<EncString>()?,
+            )
+            .map_err(|_| eyre!("Invalid master password."))?;
+        let user_key = SymmetricKey::from_slice(&user_key)?;
+
+        println!("Your vault is now unlocked!");
+        println!();
+        ctx.store_user_key(&profile, &mut account, &user_key)
+    }
+}
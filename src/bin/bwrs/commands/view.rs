@@ -0,0 +1,115 @@
+//! Decrypted vault objects, shaped like the official CLI's JSON output.
+
+use bwrs::{
+    crypto::{EncString, SymmetricKey},
+    vault::Cipher,
+};
+use eyre::{eyre, OptionExt, Result, WrapErr};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::Keys;
+
+/// The elements of a list in the vault cache, treating `null` as empty.
+pub fn elements(value: &Value) -> impl Iterator<Item = &Value> {
+    value.as_array().into_iter().flatten()
+}
+
+/// Parse an optional id, such as a cipher's `organizationId`.
+pub fn id(value: &Value) -> Result<Option<Uuid>> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(id) => Ok(Some(id.parse()?)),
+        _ => Err(eyre!("Expected an id")),
+    }
+}
+
+/// Find an object in one of the vault cache's lists by id.
+pub fn find(list: &Value, id: Uuid) -> Result<&Value> {
+    elements(list)
+        .find(|object| self::id(&object["id"]).ok().flatten() == Some(id))
+        .ok_or_eyre("Not found.")
+}
+
+/// Decrypt an encrypted string, leaving `null` as it is.
+fn decrypt(key: &SymmetricKey, value: &Value) -> Result<Value> {
+    match value {
+        Value::Null => Ok(Value::Null),
+        Value::String(enc) => Ok(key.decrypt_str(&enc.parse::<EncString>()?)?.into()),
+        _ => Err(eyre!("Expected an encrypted string")),
+    }
+}
+
+/// Decrypt a cipher from the vault cache.
+pub fn item(cipher: &Value, keys: &Keys) -> Result<Cipher> {
+    let key = keys
+        .get(id(&cipher["organizationId"])?)
+        .wrap_err_with(|| format!("Failed to decrypt item {}", cipher["id"]))?;
+
+    Cipher::decrypt(cipher, key)
+}
+
+pub fn contains(value: &str, search: &str) -> bool {
+    value.to_lowercase().contains(search)
+}
+
+/// Whether an item matches a lowercase search term, the way the official clients search the
+/// vault: by name, subtitle or URI, or by id prefix for longer terms.
+pub fn matches_search(item: &Cipher, search: &str) -> bool {
+    contains(&item.name, search)
+        || (search.len() >= 8 && item.id.is_some_and(|id| id.to_string().starts_with(search)))
+        || item
+            .subtitle()
+            .is_some_and(|subtitle| contains(&subtitle, search))
+        || item.login().is_some_and(|login| {
+            login
+                .uris
+                .iter()
+                .any(|uri| uri.uri.as_deref().is_some_and(|uri| contains(uri, search)))
+        })
+}
+
+pub fn folder(folder: &Value, keys: &Keys) -> Result<Value> {
+    Ok(json!({
+        "object": "folder",
+        "id": folder["id"],
+        "name": decrypt(&keys.user_key, &folder["name"])
+            .wrap_err_with(|| format!("Failed to decrypt folder {}", folder["id"]))?,
+    }))
+}
+
+/// The pseudo-folder holding items which aren't in a folder.
+pub fn no_folder() -> Value {
+    json!({
+        "object": "folder",
+        "id": null,
+        "name": "No Folder",
+    })
+}
+
+pub fn collection(collection: &Value, keys: &Keys) -> Result<Value> {
+    let name = keys
+        .get(id(&collection["organizationId"])?)
+        .and_then(|key| decrypt(key, &collection["name"]))
+        .wrap_err_with(|| format!("Failed to decrypt collection {}", collection["id"]))?;
+
+    Ok(json!({
+        "object": "collection",
+        "id": collection["id"],
+        "organizationId": collection["organizationId"],
+        "name": name,
+        "externalId": collection["externalId"],
+    }))
+}
+
+/// An organization the user belongs to, from the vault cache's profile.
+pub fn organization(organization: &Value) -> Value {
+    json!({
+        "object": "organization",
+        "id": organization["id"],
+        "name": organization["name"],
+        "status": organization["status"],
+        "type": organization["type"],
+        "enabled": organization["enabled"],
+    })
+}
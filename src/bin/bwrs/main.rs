@@ -2,10 +2,19 @@ use clap::{Parser, Subcommand};
 use eyre::Result;
 
 mod commands;
-use commands::{CliCommand, Completion, Encode, Login};
+use commands::{
+    Account, CliCommand, Completion, Config, Confirm, Context, Create, Delete, Edit, Encode,
+    Generate, Get, List, Lock, Login, Logout, Move, Restore, Status, Sync, Unlock,
+};
 
 #[derive(Parser)]
 pub struct Cli {
+    /// The account to use, instead of the active one.
+    #[arg(long, global = true, env = "BWRS_ACCOUNT")]
+    account: Option<String>,
+    /// Don't start or use bwrsd.
+    #[arg(long, global = true, env = "BWRS_NO_AGENT")]
+    no_agent: bool,
     #[command(subcommand)]
     command: Command,
 }
@@ -13,26 +22,46 @@ pub struct Cli {
 enum Command {
     /// Log into a user account.
     Login(<Login as CliCommand>::Args),
-    Logout,
-    Lock,
-    Unlock,
-    Sync,
-    Generate,
+    /// Log out of the current user account, revoking its tokens and removing local data.
+    Logout(<Logout as CliCommand>::Args),
+    /// Lock the vault, forgetting the session key and dropping keys held by bwrsd.
+    Lock(<Lock as CliCommand>::Args),
+    /// Unlock the vault with the master password.
+    Unlock(<Unlock as CliCommand>::Args),
+    /// Pull the latest vault data from the server.
+    Sync(<Sync as CliCommand>::Args),
+    /// Generate a password or passphrase.
+    Generate(<Generate as CliCommand>::Args),
     /// Base 64 encode stdin.
     Encode(<Encode as CliCommand>::Args),
-    Config,
+    /// Configure bwrs.
+    Config(<Config as CliCommand>::Args),
     Update,
+    /// Manage the accounts stored on this device.
+    Account(<Account as CliCommand>::Args),
     /// Generate shell completions.
     Completion(<Completion as CliCommand>::Args),
-    Status,
-    List,
-    Get,
-    Create,
-    Edit,
-    Delete,
-    Restore,
-    Move,
-    Confirm,
+    /// Show the server, user and lock status as JSON.
+    Status(<Status as CliCommand>::Args),
+    /// List decrypted items, folders, collections or organizations from the local vault as JSON.
+    List(<List as CliCommand>::Args),
+    /// Get a decrypted item, one of its details, an attachment, or a template.
+    Get(<Get as CliCommand>::Args),
+    /// Create an item, folder, attachment or organization collection.
+    Create(<Create as CliCommand>::Args),
+    /// Edit an item, folder, an item's collections, or an organization collection.
+    Edit(<Edit as CliCommand>::Args),
+    /// Delete an item, folder, attachment or organization collection. Items are moved to the
+    /// trash unless `--permanent` is given.
+    Delete(<Delete as CliCommand>::Args),
+    /// Restore an item from the trash.
+    Restore(<Restore as CliCommand>::Args),
+    /// Move an item into an organization's collections, encrypting it with the organization's
+    /// key.
+    Move(<Move as CliCommand>::Args),
+    /// Confirm a member who has accepted an invitation to an organization, after checking their
+    /// fingerprint phrase.
+    Confirm(<Confirm as CliCommand>::Args),
     Import,
     Export,
     Share,
@@ -44,27 +73,32 @@ enum Command {
 
 fn main() -> Result<()> {
     let args = Cli::parse();
+    let ctx = Context {
+        account: args.account,
+        no_agent: args.no_agent,
+    };
 
     match args.command {
-        Command::Login(args) => Login::handle(args),
-        Command::Logout => todo!(),
-        Command::Lock => todo!(),
-        Command::Unlock => todo!(),
-        Command::Sync => todo!(),
-        Command::Generate => todo!(),
-        Command::Encode(args) => Encode::handle(args),
-        Command::Config => todo!(),
+        Command::Login(args) => Login::handle(&ctx, args),
+        Command::Logout(args) => Logout::handle(&ctx, args),
+        Command::Lock(args) => Lock::handle(&ctx, args),
+        Command::Unlock(args) => Unlock::handle(&ctx, args),
+        Command::Sync(args) => Sync::handle(&ctx, args),
+        Command::Generate(args) => Generate::handle(&ctx, args),
+        Command::Encode(args) => Encode::handle(&ctx, args),
+        Command::Config(args) => Config::handle(&ctx, args),
         Command::Update => todo!(),
-        Command::Completion(args) => Completion::handle(args),
-        Command::Status => todo!(),
-        Command::List => todo!(),
-        Command::Get => todo!(),
-        Command::Create => todo!(),
-        Command::Edit => todo!(),
-        Command::Delete => todo!(),
-        Command::Restore => todo!(),
-        Command::Move => todo!(),
-        Command::Confirm => todo!(),
+        Command::Account(args) => Account::handle(&ctx, args),
+        Command::Completion(args) => Completion::handle(&ctx, args),
+        Command::Status(args) => Status::handle(&ctx, args),
+        Command::List(args) => List::handle(&ctx, args),
+        Command::Get(args) => Get::handle(&ctx, args),
+        Command::Create(args) => Create::handle(&ctx, args),
+        Command::Edit(args) => Edit::handle(&ctx, args),
+        Command::Delete(args) => Delete::handle(&ctx, args),
+        Command::Restore(args) => Restore::handle(&ctx, args),
+        Command::Move(args) => Move::handle(&ctx, args),
+        Command::Confirm(args) => Confirm::handle(&ctx, args),
         Command::Import => todo!(),
         Command::Export => todo!(),
         Command::Share => todo!(),
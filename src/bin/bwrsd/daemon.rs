@@ -0,0 +1,199 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Seek, Write},
+    os::{
+        fd::AsRawFd,
+        unix::fs::{DirBuilderExt, OpenOptionsExt},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bwrs::agent::{pid_path, socket_path};
+use eyre::{eyre, Result, WrapErr};
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+use crate::keys::KeyStore;
+
+/// An exclusively locked pidfile, proving this is the only agent running for the user.
+pub struct PidFile {
+    file: File,
+}
+impl PidFile {
+    pub fn acquire() -> Result<Self> {
+        let path = pid_path()?;
+        if let Some(dir) = path.parent() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(&path)
+            .wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+
+        if !try_lock(&file)? {
+            let mut pid = String::new();
+            file.read_to_string(&mut pid)?;
+
+            return Err(eyre!("bwrsd is already running (pid {})", pid.trim()));
+        }
+
+        Ok(Self { file })
+    }
+
+    /// Record the current process id, which changes when daemonizing.
+    pub fn write_pid(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        writeln!(self.file, "{}", std::process::id())?;
+
+        Ok(())
+    }
+}
+
+/// Try to take an exclusive lock, returning `false` if another process holds it.
+fn try_lock(file: &File) -> Result<bool> {
+    // SAFETY: the descriptor is valid for as long as `file` is borrowed.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+
+    match std::io::Error::last_os_error() {
+        e if e.kind() == ErrorKind::WouldBlock => Ok(false),
+        e => Err(e.into()),
+    }
+}
+
+/// The pid of the running agent, if there is one.
+pub fn running_pid() -> Result<Option<libc::pid_t>> {
+    let mut file = match File::open(pid_path()?) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    // If we can take the lock, whoever wrote the pidfile has exited.
+    if try_lock(&file)? {
+        return Ok(None);
+    }
+
+    let mut pid = String::new();
+    file.read_to_string(&mut pid)?;
+
+    Ok(Some(pid.trim().parse()?))
+}
+
+/// On SIGTERM, SIGINT or SIGHUP, zeroize every held key and clean up before exiting.
+pub fn spawn_signal_handler(keys: Arc<Mutex<KeyStore>>, ssh_socket: Option<PathBuf>) -> Result<()> {
+    let mut signals = Signals::new([SIGTERM, SIGINT, SIGHUP])?;
+
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            // A panicking client thread shouldn't stop the keys from being dropped.
+            keys.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .lock(None);
+
+            for path in [socket_path(), pid_path()].into_iter().flatten() {
+                let _ = fs::remove_file(path);
+            }
+            if let Some(path) = &ssh_socket {
+                let _ = fs::remove_file(path);
+            }
+            eprintln!("Exiting on signal {signal}");
+
+            std::process::exit(0);
+        }
+    });
+
+    Ok(())
+}
+
+/// Ask the running agent to exit, waiting for it to release the pidfile.
+pub fn stop() -> Result<()> {
+    let pid = running_pid()?.ok_or_else(|| eyre!("bwrsd is not running"))?;
+
+    // SAFETY: kill has no memory safety requirements.
+    if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .wrap_err_with(|| format!("Failed to stop bwrsd (pid {pid})"));
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while running_pid()?.is_some() {
+        if Instant::now() > deadline {
+            return Err(eyre!("bwrsd (pid {pid}) didn't exit after SIGTERM"));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    Ok(())
+}
+
+/// Where a daemonized agent writes its output by default.
+pub fn default_log_path() -> Result<PathBuf> {
+    Ok(dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .ok_or_else(|| eyre!("Unable to find a state directory"))?
+        .join(env!("CARGO_PKG_NAME"))
+        .join("bwrsd.log"))
+}
+
+/// Detach from the terminal: fork twice with a `setsid` in between so the agent can never
+/// reacquire a controlling terminal, then point stdio at `/dev/null` and the log file.
+///
+/// This must be called before any threads are spawned.
+pub fn daemonize(log_path: &Path) -> Result<()> {
+    if let Some(dir) = log_path.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+    }
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(log_path)
+        .wrap_err_with(|| format!("Failed to open {}", log_path.display()))?;
+    let null = File::open("/dev/null")?;
+
+    fork_and_exit_parent()?;
+    // SAFETY: setsid has no memory safety requirements.
+    if unsafe { libc::setsid() } < 0 {
+        return Err(std::io::Error::last_os_error()).wrap_err("Failed to create a new session");
+    }
+    fork_and_exit_parent()?;
+
+    std::env::set_current_dir("/")?;
+    // SAFETY: the descriptors are all valid, and dup2 atomically replaces the standard ones.
+    unsafe {
+        libc::umask(0o077);
+        libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO);
+        libc::dup2(log.as_raw_fd(), libc::STDOUT_FILENO);
+        libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO);
+    }
+
+    Ok(())
+}
+
+fn fork_and_exit_parent() -> Result<()> {
+    // SAFETY: we're single threaded, so the child can safely keep running Rust code.
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()).wrap_err("Failed to fork process"),
+        0 => Ok(()),
+        _ => std::process::exit(0),
+    }
+}
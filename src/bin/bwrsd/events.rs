@@ -0,0 +1,26 @@
+use std::sync::{
+    mpsc::{self, Receiver, Sender},
+    Mutex,
+};
+
+use bwrs::agent::Event;
+
+/// Clients which asked to be told about [`Event`]s.
+#[derive(Default)]
+pub struct Subscribers(Mutex<Vec<Sender<Event>>>);
+impl Subscribers {
+    pub fn add(&self) -> Receiver<Event> {
+        let (tx, rx) = mpsc::channel();
+        self.0.lock().unwrap().push(tx);
+
+        rx
+    }
+
+    /// Send an event to every subscriber, forgetting those which have disconnected.
+    pub fn notify(&self, event: &Event) {
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
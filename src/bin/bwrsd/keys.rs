@@ -0,0 +1,283 @@
+use std::{
+    alloc::{self, Layout},
+    collections::{BTreeMap, HashMap},
+    ptr::NonNull,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bwrs::{agent::Event, crypto::SymmetricKey, storage::Profile};
+use eyre::{OptionExt, Result};
+use uuid::Uuid;
+
+use crate::events::Subscribers;
+
+/// A key pinned in memory with `mlock`, so it's never written to swap. Each key gets a page of
+/// its own, since `munlock` isn't reference counted: unlocking memory shared with another key
+/// would leave that key swappable. The key is zeroized before its page is unlocked.
+pub struct LockedKey {
+    key: NonNull<SymmetricKey>,
+    layout: Layout,
+}
+// SAFETY: `LockedKey` owns its page exclusively, like a `Box<SymmetricKey>` would.
+unsafe impl Send for LockedKey {}
+// SAFETY: the key is only ever read through a shared reference.
+unsafe impl Sync for LockedKey {}
+impl LockedKey {
+    pub fn new(key: SymmetricKey) -> Self {
+        // SAFETY: sysconf has no preconditions.
+        let page_size = usize::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) })
+            .unwrap_or(4096)
+            .max(std::mem::align_of::<SymmetricKey>());
+        let layout = Layout::from_size_align(
+            std::mem::size_of::<SymmetricKey>().next_multiple_of(page_size),
+            page_size,
+        )
+        .expect("page sized layout is valid");
+
+        // SAFETY: the layout has a non-zero size.
+        let page = unsafe { alloc::alloc_zeroed(layout) };
+        let Some(page) = NonNull::new(page.cast::<SymmetricKey>()) else {
+            alloc::handle_alloc_error(layout);
+        };
+        // SAFETY: the page is owned by this key until it's freed on drop. Failing to lock (e.g.
+        // due to RLIMIT_MEMLOCK) isn't fatal, the key is still zeroized.
+        unsafe {
+            libc::mlock(page.as_ptr().cast(), layout.size());
+            page.as_ptr().write(key);
+        }
+
+        Self { key: page, layout }
+    }
+    pub fn key(&self) -> &SymmetricKey {
+        // SAFETY: the key was written in `new` and is only dropped with `self`.
+        unsafe { self.key.as_ref() }
+    }
+}
+impl Drop for LockedKey {
+    fn drop(&mut self) {
+        let page = self.key.as_ptr();
+        // SAFETY: the key is dropped (zeroizing it) exactly once, then the page which only it
+        // occupied is unlocked and freed with the layout it was allocated with.
+        unsafe {
+            std::ptr::drop_in_place(page);
+            libc::munlock(page.cast(), self.layout.size());
+            alloc::dealloc(page.cast(), self.layout);
+        }
+    }
+}
+
+/// The strictest `MaximumVaultTimeout` across an account's organizations.
+pub fn maximum_vault_timeout(profile: &Profile) -> Result<Option<Duration>> {
+    Ok(profile
+        .maximum_vault_timeout()?
+        .map(|minutes| Duration::from_secs(minutes * 60)))
+}
+
+/// When held keys should be dropped.
+#[derive(Clone, Copy, Default)]
+pub struct LockPolicy {
+    /// Lock once keys haven't been requested for this long.
+    pub idle_timeout: Option<Duration>,
+    /// Lock this long after unlocking, regardless of use.
+    pub absolute_timeout: Option<Duration>,
+}
+
+/// The decrypted keys for one account.
+pub struct UnlockedAccount {
+    pub user_key: LockedKey,
+    pub org_keys: BTreeMap<Uuid, LockedKey>,
+    unlocked_at: Instant,
+    last_used: Instant,
+    idle_timeout: Option<Duration>,
+}
+impl UnlockedAccount {
+    fn is_expired(&self, now: Instant, policy: &LockPolicy) -> bool {
+        let idle = self
+            .idle_timeout
+            .is_some_and(|timeout| now.duration_since(self.last_used) >= timeout);
+        let absolute = policy
+            .absolute_timeout
+            .is_some_and(|timeout| now.duration_since(self.unlocked_at) >= timeout);
+
+        idle || absolute
+    }
+}
+
+/// Every account the agent currently holds keys for. Subscribers are told whenever an account
+/// is unlocked or locked, whichever path dropped its keys.
+#[derive(Default)]
+pub struct KeyStore {
+    accounts: HashMap<String, UnlockedAccount>,
+    policy: LockPolicy,
+    subscribers: Arc<Subscribers>,
+}
+impl KeyStore {
+    pub fn new(policy: LockPolicy, subscribers: Arc<Subscribers>) -> Self {
+        Self {
+            accounts: HashMap::new(),
+            policy,
+            subscribers,
+        }
+    }
+
+    /// Hold an account's keys. `max_timeout` is the organization's `MaximumVaultTimeout`, which
+    /// caps the idle timeout.
+    pub fn unlock(
+        &mut self,
+        account: String,
+        user_key: SymmetricKey,
+        org_keys: BTreeMap<Uuid, SymmetricKey>,
+        max_timeout: Option<Duration>,
+    ) {
+        let now = Instant::now();
+        let idle_timeout = self.idle_timeout(max_timeout);
+
+        self.subscribers.notify(&Event::Unlocked {
+            account: account.clone(),
+        });
+        self.accounts.insert(
+            account,
+            UnlockedAccount {
+                user_key: LockedKey::new(user_key),
+                org_keys: org_keys
+                    .into_iter()
+                    .map(|(id, key)| (id, LockedKey::new(key)))
+                    .collect(),
+                unlocked_at: now,
+                last_used: now,
+                idle_timeout,
+            },
+        );
+    }
+    /// Get an account's keys to hand them out, counting as activity for the idle timeout.
+    pub fn get(&mut self, account: &str) -> Option<&UnlockedAccount> {
+        self.touch(account);
+        self.peek(account)
+    }
+    /// Get an account's keys without counting as activity, e.g. to check that it's unlocked or
+    /// to see what its vault holds.
+    pub fn peek(&mut self, account: &str) -> Option<&UnlockedAccount> {
+        self.unexpired(account).map(|unlocked| &*unlocked)
+    }
+    /// Count a use of an account's secrets as activity for the idle timeout.
+    pub fn touch(&mut self, account: &str) {
+        if let Some(unlocked) = self.unexpired(account) {
+            unlocked.last_used = Instant::now();
+        }
+    }
+    /// An account's keys, dropping them first if its timeout has passed.
+    fn unexpired(&mut self, account: &str) -> Option<&mut UnlockedAccount> {
+        if self
+            .accounts
+            .get(account)
+            .is_some_and(|unlocked| unlocked.is_expired(Instant::now(), &self.policy))
+        {
+            self.remove(account);
+        }
+
+        self.accounts.get_mut(account)
+    }
+    /// The idle timeout, capped by an organization's `MaximumVaultTimeout`.
+    fn idle_timeout(&self, max_timeout: Option<Duration>) -> Option<Duration> {
+        match (self.policy.idle_timeout, max_timeout) {
+            (Some(idle), Some(max)) => Some(idle.min(max)),
+            (idle, max) => idle.or(max),
+        }
+    }
+
+    /// A copy of the key which encrypts a cipher, or its own key: its organization's key if it
+    /// belongs to one, otherwise the user key. Returns `None` if the account is locked.
+    ///
+    /// This doesn't count as activity, since ciphers are also decrypted just to search them.
+    /// Callers handing out a secret should [`KeyStore::touch`] the account.
+    pub fn cipher_key(
+        &mut self,
+        account: &str,
+        organization_id: Option<Uuid>,
+    ) -> Result<Option<SymmetricKey>> {
+        let Some(unlocked) = self.peek(account) else {
+            return Ok(None);
+        };
+
+        let key = match organization_id {
+            Some(id) => unlocked
+                .org_keys
+                .get(&id)
+                .ok_or_eyre("The organization's key isn't unlocked")?,
+            None => &unlocked.user_key,
+        };
+
+        Ok(Some(SymmetricKey::from_slice(key.key().as_bytes())?))
+    }
+
+    /// Re-read what an account's keys depend on from its vault cache, after it changed: the keys
+    /// of organizations it joined, and a `MaximumVaultTimeout` which arrived or tightened. This
+    /// doesn't count as activity for the idle timeout.
+    pub fn refresh(&mut self, account: &str, profile: &Profile) -> Result<()> {
+        if !self.accounts.contains_key(account) {
+            return Ok(());
+        }
+        let Some(stored) = profile.account()? else {
+            return Ok(());
+        };
+        let idle_timeout = self.idle_timeout(maximum_vault_timeout(profile)?);
+        let Some(unlocked) = self.accounts.get_mut(account) else {
+            return Ok(());
+        };
+
+        unlocked.idle_timeout = idle_timeout;
+        unlocked.org_keys = profile
+            .organization_keys(&stored, unlocked.user_key.key())?
+            .into_iter()
+            .map(|(id, key)| (id, LockedKey::new(key)))
+            .collect();
+
+        Ok(())
+    }
+
+    /// The names of every unlocked account.
+    pub fn accounts(&self) -> Vec<String> {
+        self.accounts.keys().cloned().collect()
+    }
+
+    /// Drop the keys for one account, or every account if `None`, returning the locked names.
+    pub fn lock(&mut self, account: Option<&str>) -> Vec<String> {
+        let locked = match account {
+            Some(account) => self
+                .accounts
+                .contains_key(account)
+                .then(|| account.to_string())
+                .into_iter()
+                .collect(),
+            None => self.accounts(),
+        };
+        for name in &locked {
+            self.remove(name);
+        }
+
+        locked
+    }
+    /// Drop the keys of every account whose timeout has passed.
+    pub fn lock_expired(&mut self) -> Vec<String> {
+        let now = Instant::now();
+        let expired = self
+            .accounts
+            .iter()
+            .filter(|(_, unlocked)| unlocked.is_expired(now, &self.policy))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for name in &expired {
+            self.remove(name);
+        }
+
+        expired
+    }
+    fn remove(&mut self, account: &str) {
+        if self.accounts.remove(account).is_some() {
+            self.subscribers.notify(&Event::Locked {
+                account: account.to_string(),
+            });
+        }
+    }
+}
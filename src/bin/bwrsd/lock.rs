@@ -0,0 +1,105 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::keys::KeyStore;
+
+const TICK: Duration = Duration::from_secs(1);
+/// How much longer the boot clock may advance than the monotonic clock in one tick before we
+/// assume the machine was suspended.
+const SUSPEND_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Periodically drop keys whose timeouts have passed, and optionally every key after a suspend.
+pub fn spawn_timer(keys: Arc<Mutex<KeyStore>>, lock_on_suspend: bool) {
+    thread::spawn(move || {
+        let mut last = clocks();
+
+        loop {
+            thread::sleep(TICK);
+
+            let mut keys = keys.lock().unwrap();
+            for account in keys.lock_expired() {
+                eprintln!("Locked {account}: timed out");
+            }
+
+            let now = clocks();
+            if lock_on_suspend && was_suspended(last, now) {
+                for account in keys.lock(None) {
+                    eprintln!("Locked {account}: system was suspended");
+                }
+            }
+            last = now;
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+type Clocks = (Duration, Duration);
+#[cfg(not(target_os = "linux"))]
+type Clocks = ();
+
+/// The monotonic clock stops while suspended but the boot clock doesn't, so comparing how far
+/// each advanced reveals a suspend.
+#[cfg(target_os = "linux")]
+fn clocks() -> Clocks {
+    fn clock(id: libc::clockid_t) -> Duration {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `ts` is a valid timespec for the duration of the call.
+        unsafe { libc::clock_gettime(id, &mut ts) };
+
+        Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    }
+
+    (clock(libc::CLOCK_MONOTONIC), clock(libc::CLOCK_BOOTTIME))
+}
+#[cfg(target_os = "linux")]
+fn was_suspended(last: Clocks, now: Clocks) -> bool {
+    let monotonic = now.0.saturating_sub(last.0);
+    let boot = now.1.saturating_sub(last.1);
+
+    boot.saturating_sub(monotonic) > SUSPEND_THRESHOLD
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clocks() -> Clocks {}
+#[cfg(not(target_os = "linux"))]
+fn was_suspended(_: Clocks, _: Clocks) -> bool {
+    false
+}
+
+/// Drop every key when logind reports that our session's screen was locked.
+#[cfg(feature = "dbus")]
+pub fn spawn_screen_lock_watcher(keys: Arc<Mutex<KeyStore>>) -> eyre::Result<()> {
+    use zbus::{blocking::Connection, blocking::Proxy, zvariant::OwnedObjectPath};
+
+    let connection = Connection::system()?;
+    let manager = Proxy::new(
+        &connection,
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+    )?;
+    let session: OwnedObjectPath = manager.call("GetSessionByPID", &(std::process::id()))?;
+    let session = Proxy::new(
+        &connection,
+        "org.freedesktop.login1",
+        session,
+        "org.freedesktop.login1.Session",
+    )?;
+    let signals = session.receive_signal("Lock")?;
+
+    thread::spawn(move || {
+        for _ in signals {
+            for account in keys.lock().unwrap().lock(None) {
+                eprintln!("Locked {account}: screen was locked");
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -1,32 +1,178 @@
-use std::{env, process::Command};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
+use bwrs::{agent::ssh_socket_path, notifications::Protocol, storage::DEFAULT_AGENT_SYNC_INTERVAL};
 use clap::Parser;
-use eyre::{eyre, Context, Result};
+#[cfg(not(feature = "dbus"))]
+use eyre::eyre;
+use eyre::Result;
+#[cfg(feature = "dbus")]
+use eyre::WrapErr;
+
+mod daemon;
+mod events;
+mod keys;
+mod lock;
+mod notifications;
+#[cfg(feature = "dbus")]
+mod secrets;
+mod server;
+mod ssh;
+mod sync;
+
+use keys::{KeyStore, LockPolicy};
 
 #[derive(Parser)]
 struct Cli {
     /// Start bwrsd in the background.
     #[arg(short, long)]
     daemonize: bool,
+    /// Where to write output when daemonized.
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+    /// Stop the running bwrsd.
+    #[arg(long, conflicts_with_all = ["daemonize", "status"])]
+    stop: bool,
+    /// Report whether bwrsd is running, exiting with 1 if it isn't.
+    #[arg(long, conflicts_with = "daemonize")]
+    status: bool,
+    /// Lock an account once its keys haven't been used for this many seconds.
+    #[arg(long, value_name = "SECONDS")]
+    idle_timeout: Option<u64>,
+    /// Lock an account this many seconds after it was unlocked, even if it's in use.
+    #[arg(long, value_name = "SECONDS")]
+    absolute_timeout: Option<u64>,
+    /// Lock every account when the session's screen is locked.
+    #[arg(long)]
+    lock_on_screen_lock: bool,
+    /// Lock every account when the system is suspended.
+    #[arg(long)]
+    lock_on_suspend: bool,
+    /// Sync unlocked accounts this often. 0 disables background sync.
+    #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_AGENT_SYNC_INTERVAL)]
+    sync_interval: u64,
+    /// Don't listen for changes pushed by the server, relying on background sync alone.
+    #[arg(long)]
+    no_notifications: bool,
+    /// The SignalR protocol used for the notifications hub: messagepack or json.
+    #[arg(long, value_name = "PROTOCOL", default_value = "messagepack")]
+    hub_protocol: Protocol,
+    /// Serve the SSH keys stored in unlocked vaults over the SSH agent protocol.
+    #[arg(long)]
+    ssh_agent: bool,
+    /// Where to listen for SSH clients, instead of `ssh-agent.sock` next to the agent socket.
+    #[arg(long, value_name = "PATH", requires = "ssh_agent")]
+    ssh_socket: Option<PathBuf>,
+    /// Run this shell command before each use of an SSH key, only signing if it succeeds. The
+    /// key is described in $BWRS_SSH_KEY_NAME, $BWRS_SSH_KEY_FINGERPRINT and $BWRS_ACCOUNT.
+    #[arg(long, value_name = "COMMAND", requires = "ssh_agent")]
+    ssh_confirm: Option<String>,
+    /// Let libsecret clients read logins and notes from unlocked vaults, by registering
+    /// org.freedesktop.secrets on the session bus.
+    #[arg(long)]
+    secret_service: bool,
+    /// Also let secret service clients store new logins, and change or delete items. They're
+    /// saved through the server, like `bwrs create`, `edit` and `delete`.
+    #[arg(long, requires = "secret_service")]
+    secret_service_writable: bool,
 }
 
 fn main() -> Result<()> {
     let args = Cli::parse();
 
-    if args.daemonize {
-        Command::new(if let Ok(exe) = env::current_exe() {
-            exe.to_string_lossy().to_string()
-        } else {
-            env!("CARGO_BIN_NAME").to_string()
-        })
-        .args(env::args().skip(1).filter(|a| a != "--daemonize"))
-        .spawn()
-        .wrap_err(eyre!("Failed to fork process"))?;
+    if args.status {
+        match daemon::running_pid()? {
+            Some(pid) => println!("bwrsd is running (pid {pid})"),
+            None => {
+                println!("bwrsd is not running");
+                std::process::exit(1);
+            }
+        }
 
-        println!("Successfully daemonized!");
+        return Ok(());
+    }
+    if args.stop {
+        daemon::stop()?;
+        println!("Stopped bwrsd.");
 
         return Ok(());
     }
 
-    loop {}
+    let mut pidfile = daemon::PidFile::acquire()?;
+    if args.daemonize {
+        let log_file = match args.log_file {
+            Some(path) => path,
+            None => daemon::default_log_path()?,
+        };
+        println!("Successfully daemonized! Logging to {}", log_file.display());
+
+        daemon::daemonize(&log_file)?;
+    }
+    pidfile.write_pid()?;
+
+    let subscribers = Arc::new(events::Subscribers::default());
+    let keys = Arc::new(Mutex::new(KeyStore::new(
+        LockPolicy {
+            idle_timeout: args.idle_timeout.map(Duration::from_secs),
+            absolute_timeout: args.absolute_timeout.map(Duration::from_secs),
+        },
+        Arc::clone(&subscribers),
+    )));
+
+    let ssh_socket = match (args.ssh_agent, args.ssh_socket) {
+        (true, Some(path)) => Some(path),
+        (true, None) => Some(ssh_socket_path()?),
+        (false, _) => None,
+    };
+
+    daemon::spawn_signal_handler(Arc::clone(&keys), ssh_socket.clone())?;
+    lock::spawn_timer(Arc::clone(&keys), args.lock_on_suspend);
+    if args.lock_on_screen_lock {
+        #[cfg(feature = "dbus")]
+        lock::spawn_screen_lock_watcher(Arc::clone(&keys))
+            .wrap_err("Failed to watch for screen locks")?;
+        #[cfg(not(feature = "dbus"))]
+        return Err(eyre!(
+            "--lock-on-screen-lock requires bwrsd to be built with the `dbus` feature"
+        ));
+    }
+
+    if args.sync_interval > 0 {
+        sync::spawn(
+            Arc::clone(&keys),
+            Arc::clone(&subscribers),
+            Duration::from_secs(args.sync_interval),
+        );
+    }
+
+    if !args.no_notifications {
+        notifications::spawn(
+            Arc::clone(&keys),
+            Arc::clone(&subscribers),
+            args.hub_protocol,
+        );
+    }
+
+    if let Some(path) = &ssh_socket {
+        ssh::spawn(path, Arc::clone(&keys), args.ssh_confirm)?;
+    }
+
+    if args.secret_service {
+        #[cfg(feature = "dbus")]
+        secrets::spawn(
+            Arc::clone(&keys),
+            subscribers.add(),
+            args.secret_service_writable,
+        )
+        .wrap_err("Failed to register the secret service")?;
+        #[cfg(not(feature = "dbus"))]
+        return Err(eyre!(
+            "--secret-service requires bwrsd to be built with the `dbus` feature"
+        ));
+    }
+
+    server::run(keys, subscribers)
 }
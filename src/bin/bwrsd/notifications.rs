@@ -0,0 +1,163 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bwrs::{
+    agent::Event,
+    notifications::{Hub, Notification, Protocol},
+    storage::{Profile, Storage},
+};
+use eyre::Result;
+
+use crate::{events::Subscribers, keys::KeyStore};
+
+const TICK: Duration = Duration::from_secs(1);
+/// The first reconnection attempt after losing the hub, doubled on each further failure.
+const MIN_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// Listen to the server's notifications hub for every unlocked account, applying changes to
+/// the vault cache as they're pushed. Each account gets its own connection, which is closed
+/// once the account is locked.
+pub fn spawn(keys: Arc<Mutex<KeyStore>>, subscribers: Arc<Subscribers>, protocol: Protocol) {
+    thread::spawn(move || {
+        let listening = Arc::new(Mutex::new(HashSet::<String>::new()));
+
+        loop {
+            for account in keys.lock().unwrap().accounts() {
+                if !listening.lock().unwrap().insert(account.clone()) {
+                    continue;
+                }
+
+                let keys = Arc::clone(&keys);
+                let subscribers = Arc::clone(&subscribers);
+                let listening = Arc::clone(&listening);
+                thread::spawn(move || {
+                    listen(&account, &keys, &subscribers, protocol);
+                    listening.lock().unwrap().remove(&account);
+                });
+            }
+
+            thread::sleep(TICK);
+        }
+    });
+}
+
+fn is_unlocked(keys: &Mutex<KeyStore>, account: &str) -> bool {
+    keys.lock()
+        .unwrap()
+        .accounts()
+        .iter()
+        .any(|unlocked| unlocked == account)
+}
+
+/// Stay connected for as long as the account is unlocked, reconnecting with backoff.
+fn listen(account: &str, keys: &Mutex<KeyStore>, subscribers: &Subscribers, protocol: Protocol) {
+    let mut failures = 0;
+
+    while is_unlocked(keys, account) {
+        let mut connected = false;
+        let e = match connect(account, keys, subscribers, protocol, &mut connected) {
+            Ok(()) => return,
+            Err(e) => e,
+        };
+        if connected {
+            failures = 0;
+        }
+
+        let backoff = MIN_BACKOFF
+            .saturating_mul(2u32.saturating_pow(failures))
+            .min(MAX_BACKOFF);
+        failures += 1;
+        eprintln!(
+            "Lost notifications for {account}, reconnecting in {}s: {e}",
+            backoff.as_secs()
+        );
+
+        let deadline = Instant::now() + backoff;
+        while Instant::now() < deadline && is_unlocked(keys, account) {
+            thread::sleep(TICK);
+        }
+    }
+}
+
+/// Connect to the hub and apply notifications until the account is locked or logged out.
+fn connect(
+    account: &str,
+    keys: &Mutex<KeyStore>,
+    subscribers: &Subscribers,
+    protocol: Protocol,
+    connected: &mut bool,
+) -> Result<()> {
+    let profile = Storage::open()?.profile(account)?;
+    let device_id = profile.device_id()?.to_string();
+    let url = profile.server()?.notifications_url();
+
+    let mut hub = profile.with_api(|_, access_token| Hub::connect(&url, access_token, protocol))?;
+    *connected = true;
+
+    while is_unlocked(keys, account) {
+        for message in hub.receive()? {
+            // Changes made from this device are already in the cache.
+            if message.context_id.as_deref() == Some(device_id.as_str()) {
+                continue;
+            }
+
+            match message.notification {
+                Notification::LogOut => {
+                    keys.lock().unwrap().lock(Some(account));
+                    profile.wipe()?;
+                    eprintln!("{account} was logged out by the server");
+                    subscribers.notify(&Event::LoggedOut {
+                        account: account.to_string(),
+                    });
+
+                    return Ok(());
+                }
+                Notification::AuthRequest { id } => subscribers.notify(&Event::AuthRequest {
+                    account: account.to_string(),
+                    id,
+                }),
+                Notification::SyncVault => {
+                    if profile.sync()? {
+                        keys.lock().unwrap().refresh(account, &profile)?;
+                        subscribers.notify(&Event::VaultChanged {
+                            account: account.to_string(),
+                        });
+                    }
+                }
+                notification => {
+                    if apply(&profile, notification)? {
+                        subscribers.notify(&Event::VaultChanged {
+                            account: account.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Apply a change to the vault cache, returning whether it changed.
+fn apply(profile: &Profile, notification: Notification) -> Result<bool> {
+    match notification {
+        Notification::SyncCipherUpdate { id } => {
+            let cipher =
+                profile.with_api(|client, access_token| client.cipher(access_token, id))?;
+            profile.upsert("ciphers", id, cipher)
+        }
+        Notification::SyncCipherDelete { id } => profile.upsert("ciphers", id, None),
+        Notification::SyncFolderUpdate { id } => {
+            let folder =
+                profile.with_api(|client, access_token| client.folder(access_token, id))?;
+            profile.upsert("folders", id, folder)
+        }
+        Notification::SyncFolderDelete { id } => profile.remove_folder(id),
+        _ => Ok(false),
+    }
+}
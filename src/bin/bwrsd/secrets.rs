@@ -0,0 +1,976 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Receiver,
+        Arc, Mutex,
+    },
+    thread,
+    time::SystemTime,
+};
+
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use bwrs::{
+    agent::Event,
+    crypto::SymmetricKey,
+    storage::{Storage, DEFAULT_PROFILE},
+    vault::{Cipher, CipherKind, Field, FieldType, Login, LoginUri},
+};
+use cbc::cipher::block_padding::Pkcs7;
+use eyre::OptionExt;
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, RngCore};
+use rsa::BigUint;
+use serde_json::Value;
+use sha2::Sha256;
+use uuid::Uuid;
+use zbus::{
+    interface,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value as Variant},
+    ObjectServer,
+};
+use zeroize::Zeroizing;
+
+use crate::keys::KeyStore;
+
+const SERVICE_NAME: &str = "org.freedesktop.secrets";
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+
+const LABEL: &str = "org.freedesktop.Secret.Item.Label";
+const ATTRIBUTES: &str = "org.freedesktop.Secret.Item.Attributes";
+
+const CIPHER_LOGIN: u64 = 1;
+const CIPHER_SECURE_NOTE: u64 = 2;
+
+/// The Oakley group 2 prime from RFC 2409, used by `dh-ietf1024-sha256-aes128-cbc-pkcs7`.
+const DH_PRIME: &str = "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74\
+                        020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437\
+                        4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED\
+                        EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE65381FFFFFFFFFFFFFFFF";
+
+/// A secret as sent over the bus: the session, encryption parameters, value and content type.
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+#[derive(zbus::DBusError, Debug)]
+#[zbus(prefix = "org.freedesktop")]
+enum Error {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    #[zbus(name = "Secret.Error.IsLocked")]
+    IsLocked(String),
+    #[zbus(name = "Secret.Error.NoSession")]
+    NoSession(String),
+    #[zbus(name = "Secret.Error.NoSuchObject")]
+    NoSuchObject(String),
+    #[zbus(name = "DBus.Error.NotSupported")]
+    NotSupported(String),
+    #[zbus(name = "DBus.Error.Failed")]
+    Failed(String),
+}
+impl From<eyre::Report> for Error {
+    fn from(e: eyre::Report) -> Self {
+        Self::Failed(e.to_string())
+    }
+}
+impl From<zbus::zvariant::Error> for Error {
+    fn from(e: zbus::zvariant::Error) -> Self {
+        Self::ZBus(e.into())
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+fn read_only<T>() -> Result<T> {
+    Err(Error::NotSupported("The vault is read-only".into()))
+}
+/// Collections are accounts, which are only added and removed by `bwrs`.
+fn accounts_only<T>() -> Result<T> {
+    Err(Error::NotSupported(
+        "Collections are accounts, which are managed with bwrs".into(),
+    ))
+}
+
+/// State shared by every object on the bus.
+struct State {
+    keys: Arc<Mutex<KeyStore>>,
+    /// Whether clients may store, change and delete items.
+    writable: bool,
+    /// The AES key of each open session, or `None` for sessions using the `plain` algorithm.
+    sessions: Mutex<HashMap<OwnedObjectPath, Option<Zeroizing<[u8; 16]>>>>,
+    next_session: AtomicU64,
+    /// The collection and item objects currently on the bus.
+    registered: Mutex<HashSet<OwnedObjectPath>>,
+}
+impl State {
+    fn check_writable(&self) -> Result<()> {
+        if self.writable {
+            Ok(())
+        } else {
+            read_only()
+        }
+    }
+
+    fn is_unlocked(&self, account: &str) -> bool {
+        self.keys
+            .lock()
+            .unwrap()
+            .accounts()
+            .iter()
+            .any(|unlocked| unlocked == account)
+    }
+
+    /// Wrap a secret for a session, encrypting it if the session negotiated a key.
+    fn encode_secret(&self, session: &ObjectPath<'_>, secret: &[u8]) -> Result<Secret> {
+        let sessions = self.sessions.lock().unwrap();
+        let key = sessions
+            .get(&OwnedObjectPath::from(session.clone()))
+            .ok_or_else(|| Error::NoSession(format!("No session at {session}")))?;
+
+        let (parameters, value) = match key {
+            None => (Vec::new(), secret.to_vec()),
+            Some(key) => {
+                let mut iv = [0; 16];
+                OsRng.fill_bytes(&mut iv);
+                let value = cbc::Encryptor::<aes::Aes128>::new(key.as_ref().into(), &iv.into())
+                    .encrypt_padded_vec_mut::<Pkcs7>(secret);
+
+                (iv.to_vec(), value)
+            }
+        };
+
+        Ok((
+            session.clone().into(),
+            parameters,
+            value,
+            "text/plain; charset=utf8".to_string(),
+        ))
+    }
+
+    /// Unwrap a secret sent by a client, decrypting it if its session negotiated a key.
+    fn decode_secret(&self, secret: &Secret) -> Result<Zeroizing<String>> {
+        let (session, parameters, value, _) = secret;
+        let sessions = self.sessions.lock().unwrap();
+        let key = sessions
+            .get(session)
+            .ok_or_else(|| Error::NoSession(format!("No session at {}", session.as_str())))?;
+
+        let value = Zeroizing::new(match key {
+            None => value.clone(),
+            Some(key) => {
+                let iv = <[u8; 16]>::try_from(parameters.as_slice())
+                    .map_err(|_| Error::Failed("Expected a 16 byte IV".into()))?;
+                cbc::Decryptor::<aes::Aes128>::new(key.as_ref().into(), &iv.into())
+                    .decrypt_padded_vec_mut::<Pkcs7>(value)
+                    .map_err(|_| Error::Failed("Failed to decrypt the secret".into()))?
+            }
+        });
+
+        Ok(Zeroizing::new(
+            std::str::from_utf8(&value)
+                .map_err(|_| Error::Failed("The secret isn't UTF-8 text".into()))?
+                .to_string(),
+        ))
+    }
+
+    /// The key which encrypts an account's items, or its organization's.
+    fn key(&self, account: &str, organization_id: Option<Uuid>) -> Result<SymmetricKey> {
+        self.keys
+            .lock()
+            .unwrap()
+            .cipher_key(account, organization_id)?
+            .ok_or_else(|| Error::IsLocked(format!("{account} is locked")))
+    }
+}
+
+/// Expose the vault on the session bus as `org.freedesktop.secrets`, so libsecret clients can
+/// look up logins and notes by attribute. Collections are locked whenever the agent doesn't
+/// hold the account's keys, and the object tree is refreshed whenever a vault changes or an
+/// account is locked or unlocked. Clients can only store, change and delete items if
+/// `writable` is set.
+pub fn spawn(
+    keys: Arc<Mutex<KeyStore>>,
+    events: Receiver<Event>,
+    writable: bool,
+) -> eyre::Result<()> {
+    let state = Arc::new(State {
+        keys,
+        writable,
+        sessions: Mutex::default(),
+        next_session: AtomicU64::new(0),
+        registered: Mutex::default(),
+    });
+
+    let connection = zbus::blocking::connection::Builder::session()?
+        .serve_at(
+            SERVICE_PATH,
+            Service {
+                state: Arc::clone(&state),
+            },
+        )?
+        .build()?;
+    zbus::block_on(refresh(connection.object_server().inner(), &state))?;
+    // Only claim the name once the tree is in place, so the first client sees every item.
+    connection.request_name(SERVICE_NAME)?;
+
+    thread::spawn(move || {
+        for event in events {
+            let toggled = match &event {
+                Event::Unlocked { account } | Event::Locked { account } => Some(account),
+                Event::VaultChanged { .. } | Event::LoggedOut { .. } => None,
+                Event::AuthRequest { .. } => continue,
+            };
+
+            let server = connection.object_server();
+            let refreshed = zbus::block_on(async {
+                refresh(server.inner(), &state).await?;
+                match toggled {
+                    Some(account) => locked_changed(server.inner(), &state, account).await,
+                    None => Ok(()),
+                }
+            });
+            if let Err(e) = refreshed {
+                eprintln!("Failed to refresh secret service items: {e}");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Escape an account name for use in an object path, where only `[A-Za-z0-9_]` is allowed.
+fn collection_path(account: &str) -> OwnedObjectPath {
+    let mut path = format!("{SERVICE_PATH}/collection/");
+    for c in account.chars() {
+        if c.is_ascii_alphanumeric() {
+            path.push(c);
+        } else {
+            path.push_str(&format!("_{:02x}", c as u32));
+        }
+    }
+
+    OwnedObjectPath::try_from(path).expect("escaped paths are valid")
+}
+fn item_path(account: &str, id: Uuid) -> OwnedObjectPath {
+    OwnedObjectPath::try_from(format!(
+        "{}/{}",
+        collection_path(account).as_str(),
+        id.simple()
+    ))
+    .expect("escaped paths are valid")
+}
+
+/// The account owning a collection or item path.
+fn account_of(path: &ObjectPath<'_>) -> eyre::Result<Option<String>> {
+    Ok(Storage::open()?.profiles()?.into_iter().find(|account| {
+        let collection = collection_path(account);
+        path.as_str() == collection.as_str()
+            || path
+                .as_str()
+                .strip_prefix(collection.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+    }))
+}
+
+/// The logins and notes in an account's vault cache.
+fn ciphers(account: &str) -> eyre::Result<Vec<Value>> {
+    let vault = Storage::open()?.profile(account)?.vault()?;
+
+    Ok(vault
+        .and_then(|mut vault| match vault["ciphers"].take() {
+            Value::Array(ciphers) => Some(ciphers),
+            _ => None,
+        })
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|cipher| {
+            matches!(
+                cipher["type"].as_u64(),
+                Some(CIPHER_LOGIN | CIPHER_SECURE_NOTE)
+            ) && cipher["deletedDate"].is_null()
+        })
+        .collect())
+}
+fn cipher_id(cipher: &Value) -> Option<Uuid> {
+    cipher["id"].as_str()?.parse().ok()
+}
+/// The login or note at an item path.
+fn cipher_at(account: &str, path: &OwnedObjectPath) -> eyre::Result<Option<Value>> {
+    Ok(ciphers(account)?
+        .into_iter()
+        .find(|cipher| cipher_id(cipher).is_some_and(|id| item_path(account, id) == *path)))
+}
+fn organization_id(cipher: &Value) -> eyre::Result<Option<Uuid>> {
+    Ok(match cipher["organizationId"].as_str() {
+        Some(id) => Some(id.parse()?),
+        None => None,
+    })
+}
+
+/// Put a collection on the bus for every account and an item for every login and note, and
+/// take down those which no longer exist.
+async fn refresh(server: &ObjectServer, state: &Arc<State>) -> eyre::Result<()> {
+    let mut collections = Vec::new();
+    let mut items = Vec::new();
+    for account in Storage::open()?.profiles()? {
+        for id in ciphers(&account)?.iter().filter_map(cipher_id) {
+            items.push((item_path(&account, id), account.clone(), id));
+        }
+        collections.push((collection_path(&account), account));
+    }
+
+    let wanted = collections
+        .iter()
+        .map(|(path, _)| path)
+        .chain(items.iter().map(|(path, ..)| path))
+        .cloned()
+        .collect::<HashSet<_>>();
+    let stale = {
+        let mut registered = state.registered.lock().unwrap();
+        let stale = registered.difference(&wanted).cloned().collect::<Vec<_>>();
+        *registered = wanted;
+        stale
+    };
+
+    for path in stale {
+        // Each path only holds one of the two.
+        match server.remove::<Item, _>(&path).await {
+            Err(zbus::Error::InterfaceNotFound) => server.remove::<Collection, _>(&path).await?,
+            removed => removed?,
+        };
+    }
+    for (path, account) in collections {
+        let state = Arc::clone(state);
+        server.at(path, Collection { account, state }).await?;
+    }
+    for (path, account, id) in items {
+        let state = Arc::clone(state);
+        server.at(path, Item { account, id, state }).await?;
+    }
+
+    Ok(())
+}
+
+/// Tell clients that an account's collection and items were locked or unlocked, since they
+/// cache properties. An item's label and attributes can only be read while it's unlocked, so
+/// those change too.
+async fn locked_changed(server: &ObjectServer, state: &State, account: &str) -> eyre::Result<()> {
+    let collection = collection_path(account);
+    let items = {
+        let registered = state.registered.lock().unwrap();
+        // The account may have logged out or been removed.
+        if !registered.contains(&collection) {
+            return Ok(());
+        }
+
+        registered
+            .iter()
+            .filter(|path| {
+                path.as_str()
+                    .strip_prefix(collection.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    let interface = server.interface::<_, Collection>(&collection).await?;
+    interface
+        .get()
+        .await
+        .locked_changed(interface.signal_context())
+        .await?;
+    for path in items {
+        let interface = server.interface::<_, Item>(&path).await?;
+        let context = interface.signal_context();
+        let item = interface.get().await;
+        item.locked_changed(context).await?;
+        item.label_changed(context).await?;
+        item.attributes_changed(context).await?;
+    }
+
+    Ok(())
+}
+
+/// A decrypted login or note.
+struct Decrypted {
+    label: String,
+    attributes: HashMap<String, String>,
+    uris: Vec<String>,
+    secret: Zeroizing<String>,
+}
+impl Decrypted {
+    /// Whether every attribute matches. `uri` matches any of a login's URIs, and libsecret's
+    /// `xdg:schema` is ignored since vault items don't belong to a schema.
+    fn matches(&self, attributes: &HashMap<String, String>) -> bool {
+        attributes.iter().all(|(key, value)| match key.as_str() {
+            "xdg:schema" => true,
+            "uri" => self.uris.contains(value),
+            key => self.attributes.get(key) == Some(value),
+        })
+    }
+}
+
+/// Decrypt a cipher from the vault cache, or `None` if the account is locked.
+fn decrypt(state: &State, account: &str, cipher: &Value) -> eyre::Result<Option<Decrypted>> {
+    let Some(key) = state
+        .keys
+        .lock()
+        .unwrap()
+        .cipher_key(account, organization_id(cipher)?)?
+    else {
+        return Ok(None);
+    };
+    let cipher = Cipher::decrypt(cipher, &key)?;
+
+    let id = cipher.id.ok_or_eyre("Cipher is missing an id")?;
+    let mut attributes = HashMap::from([
+        ("bitwarden:id".to_string(), id.to_string()),
+        ("name".to_string(), cipher.name.clone()),
+    ]);
+
+    let mut uris = Vec::new();
+    if let Some(login) = cipher.login() {
+        if let Some(username) = &login.username {
+            attributes.insert("username".to_string(), username.clone());
+        }
+        uris = login
+            .uris
+            .iter()
+            .filter_map(|uri| uri.uri.clone())
+            .collect::<Vec<_>>();
+        if let Some(uri) = uris.first() {
+            attributes.insert("uri".to_string(), uri.clone());
+        }
+    }
+    // Attributes which don't map to an item's details are stored as text fields.
+    for field in &cipher.fields {
+        if let (FieldType::Text, Some(name), Some(value)) = (field.kind, &field.name, &field.value)
+        {
+            attributes
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+
+    let secret = match cipher.login() {
+        Some(login) => login.password.clone(),
+        None => cipher.notes.clone(),
+    };
+
+    Ok(Some(Decrypted {
+        label: cipher.name,
+        attributes,
+        uris,
+        secret: Zeroizing::new(secret.unwrap_or_default()),
+    }))
+}
+
+/// Find the unlocked items in an account matching `attributes`.
+fn search(
+    state: &State,
+    account: &str,
+    attributes: &HashMap<String, String>,
+) -> eyre::Result<Vec<OwnedObjectPath>> {
+    let mut found = Vec::new();
+    for cipher in ciphers(account)? {
+        let (Some(id), Some(item)) = (cipher_id(&cipher), decrypt(state, account, &cipher)?) else {
+            continue;
+        };
+        if item.matches(attributes) {
+            found.push(item_path(account, id));
+        }
+    }
+
+    Ok(found)
+}
+
+/// Store a new login with a label, the secret as its password, and attributes: `username` and
+/// `uri` fill in the login, and the rest become text fields. Returns the new item's id.
+fn create(
+    state: &State,
+    account: &str,
+    label: String,
+    attributes: &HashMap<String, String>,
+    secret: &str,
+) -> Result<Uuid> {
+    let key = state.key(account, None)?;
+    let profile = Storage::open()?.profile(account)?;
+
+    let mut cipher = Cipher {
+        name: label,
+        notes: None,
+        kind: CipherKind::Login(Login {
+            username: attributes.get("username").cloned(),
+            password: Some(secret.to_string()),
+            uris: attributes
+                .get("uri")
+                .map(|uri| LoginUri {
+                    r#match: None,
+                    uri: Some(uri.clone()),
+                })
+                .into_iter()
+                .collect(),
+            ..Login::default()
+        }),
+        fields: fields(attributes),
+        ..Cipher::template()
+    };
+    if profile.cipher_key_encryption() {
+        cipher.generate_key(&key)?;
+    }
+
+    let request = cipher.to_request(&key)?;
+    let created = profile
+        .with_api(|client, access_token| client.create_cipher(access_token, &request, &[]))?;
+    let id = cipher_id(&created).ok_or_eyre("The server returned an item without an id")?;
+    profile.upsert("ciphers", id, Some(created))?;
+    state.keys.lock().unwrap().touch(account);
+
+    Ok(id)
+}
+
+/// The attributes which don't map to a login's details, as text fields.
+fn fields(attributes: &HashMap<String, String>) -> Vec<Field> {
+    let mut fields = attributes
+        .iter()
+        .filter(|(name, _)| !["username", "uri", "name", "bitwarden:id"].contains(&name.as_str()))
+        .map(|(name, value)| Field {
+            name: Some(name.clone()),
+            value: Some(value.clone()),
+            kind: FieldType::Text,
+            linked_id: None,
+        })
+        .collect::<Vec<_>>();
+    fields.sort_by(|a, b| a.name.cmp(&b.name));
+
+    fields
+}
+
+/// Change an item through the server, recording a replaced password in its history as `bwrs
+/// edit` does, then save it to the vault cache.
+fn update(state: &State, account: &str, cipher: &Value, f: impl FnOnce(&mut Cipher)) -> Result<()> {
+    let key = state.key(account, organization_id(cipher)?)?;
+    let profile = Storage::open()?.profile(account)?;
+
+    let original = Cipher::decrypt(cipher, &key)?;
+    let id = original.id.ok_or_eyre("Cipher is missing an id")?;
+    let mut edited = original.clone();
+    f(&mut edited);
+    edited.update_password_history(&original);
+    if profile.cipher_key_encryption() {
+        edited.generate_key(&key)?;
+    }
+
+    let request = edited.to_request(&key)?;
+    let updated = profile
+        .with_api(|client, access_token| client.update_cipher(access_token, id, &request))?;
+    profile.upsert("ciphers", id, Some(updated))?;
+    state.keys.lock().unwrap().touch(account);
+
+    Ok(())
+}
+
+/// Set an item's secret: a login's password, or a note's text.
+fn set_secret(cipher: &mut Cipher, secret: &str) {
+    match cipher.login_mut() {
+        Some(login) => login.password = Some(secret.to_string()),
+        None => cipher.notes = Some(secret.to_string()),
+    }
+}
+
+/// Move an item to the trash, as `bwrs delete` does, then save it to the vault cache.
+fn delete(state: &State, account: &str, id: Uuid) -> Result<()> {
+    let profile = Storage::open()?.profile(account)?;
+
+    let cipher = profile.with_api(|client, access_token| {
+        client.soft_delete_cipher(access_token, id)?;
+        client.cipher(access_token, id)
+    })?;
+    profile.upsert("ciphers", id, cipher)?;
+    state.keys.lock().unwrap().touch(account);
+
+    Ok(())
+}
+
+fn timestamp(value: &Value) -> u64 {
+    value
+        .as_str()
+        .and_then(|date| humantime::parse_rfc3339_weak(date).ok())
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+struct Service {
+    state: Arc<State>,
+}
+#[interface(name = "org.freedesktop.Secret.Service")]
+impl Service {
+    async fn open_session(
+        &self,
+        algorithm: &str,
+        input: Variant<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> Result<(OwnedValue, OwnedObjectPath)> {
+        let (output, key) = match algorithm {
+            "plain" => (Variant::from(""), None),
+            "dh-ietf1024-sha256-aes128-cbc-pkcs7" => {
+                let Variant::Array(input) = input else {
+                    return Err(Error::Failed("Expected a public key".into()));
+                };
+                let (public_key, key) = negotiate(&Vec::<u8>::try_from(input)?)?;
+
+                (Variant::from(public_key), Some(key))
+            }
+            algorithm => {
+                return Err(Error::NotSupported(format!(
+                    "Unsupported algorithm {algorithm}"
+                )))
+            }
+        };
+
+        let n = self.state.next_session.fetch_add(1, Ordering::Relaxed);
+        let path = OwnedObjectPath::try_from(format!("{SERVICE_PATH}/session/{n}"))?;
+        self.state
+            .sessions
+            .lock()
+            .unwrap()
+            .insert(path.clone(), key);
+        server
+            .at(
+                &path,
+                Session {
+                    state: Arc::clone(&self.state),
+                },
+            )
+            .await?;
+
+        Ok((output.try_to_owned()?, path))
+    }
+
+    fn create_collection(
+        &self,
+        _properties: HashMap<String, OwnedValue>,
+        _alias: &str,
+    ) -> Result<(OwnedObjectPath, OwnedObjectPath)> {
+        self.state.check_writable()?;
+        accounts_only()
+    }
+
+    /// Search every unlocked collection. Locked items can't be matched, since their attributes
+    /// are encrypted, so the locked list is always empty.
+    async fn search_items(
+        &self,
+        attributes: HashMap<String, String>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> Result<(Vec<OwnedObjectPath>, Vec<OwnedObjectPath>)> {
+        refresh(server, &self.state).await?;
+
+        let mut unlocked = Vec::new();
+        for account in Storage::open()?.profiles()? {
+            unlocked.extend(search(&self.state, &account, &attributes)?);
+        }
+
+        Ok((unlocked, Vec::new()))
+    }
+
+    /// Keys can only be handed over by `bwrs unlock`, so this reports which of the objects are
+    /// already unlocked rather than prompting.
+    fn unlock(
+        &self,
+        objects: Vec<OwnedObjectPath>,
+    ) -> Result<(Vec<OwnedObjectPath>, OwnedObjectPath)> {
+        let mut unlocked = Vec::new();
+        for object in objects {
+            if account_of(&object)?.is_some_and(|account| self.state.is_unlocked(&account)) {
+                unlocked.push(object);
+            }
+        }
+
+        Ok((unlocked, ObjectPath::from_static_str_unchecked("/").into()))
+    }
+
+    /// Locking any object locks the whole account it belongs to.
+    fn lock(
+        &self,
+        objects: Vec<OwnedObjectPath>,
+    ) -> Result<(Vec<OwnedObjectPath>, OwnedObjectPath)> {
+        let mut locked = Vec::new();
+        for object in objects {
+            if let Some(account) = account_of(&object)? {
+                for account in self.state.keys.lock().unwrap().lock(Some(&account)) {
+                    eprintln!("Locked {account}: requested over the secret service");
+                }
+                locked.push(object);
+            }
+        }
+
+        Ok((locked, ObjectPath::from_static_str_unchecked("/").into()))
+    }
+
+    fn get_secrets(
+        &self,
+        items: Vec<OwnedObjectPath>,
+        session: ObjectPath<'_>,
+    ) -> Result<HashMap<OwnedObjectPath, Secret>> {
+        let mut secrets = HashMap::new();
+        for path in items {
+            let Some(account) = account_of(&path)? else {
+                continue;
+            };
+            let Some(cipher) = cipher_at(&account, &path)? else {
+                continue;
+            };
+
+            // Locked items are left out, as the spec requires.
+            if let Some(item) = decrypt(&self.state, &account, &cipher)? {
+                self.state.keys.lock().unwrap().touch(&account);
+                let secret = self.state.encode_secret(&session, item.secret.as_bytes())?;
+                secrets.insert(path, secret);
+            }
+        }
+
+        Ok(secrets)
+    }
+
+    /// The `default` alias is the active account's collection.
+    fn read_alias(&self, name: &str) -> Result<OwnedObjectPath> {
+        if name != "default" {
+            return Ok(ObjectPath::from_static_str_unchecked("/").into());
+        }
+
+        let active = Storage::open()?.config()?.active_account;
+        Ok(collection_path(
+            active.as_deref().unwrap_or(DEFAULT_PROFILE),
+        ))
+    }
+
+    fn set_alias(&self, _name: &str, _collection: ObjectPath<'_>) -> Result<()> {
+        self.state.check_writable()?;
+        accounts_only()
+    }
+
+    #[zbus(property)]
+    fn collections(&self) -> zbus::fdo::Result<Vec<OwnedObjectPath>> {
+        Ok(Storage::open()
+            .and_then(|storage| storage.profiles())
+            .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?
+            .iter()
+            .map(|account| collection_path(account))
+            .collect())
+    }
+}
+
+/// Complete the client's Diffie-Hellman exchange, returning our public key and the AES key
+/// derived from the shared secret.
+fn negotiate(client_public: &[u8]) -> Result<(Vec<u8>, Zeroizing<[u8; 16]>)> {
+    let prime = BigUint::parse_bytes(DH_PRIME.as_bytes(), 16).expect("the prime is valid hex");
+    let client_public = BigUint::from_bytes_be(client_public);
+    if client_public <= BigUint::from(1u8) || client_public >= &prime - 1u8 {
+        return Err(Error::Failed("Invalid public key".into()));
+    }
+
+    let mut private = Zeroizing::new([0; 128]);
+    OsRng.fill_bytes(private.as_mut());
+    let private = BigUint::from_bytes_be(private.as_ref());
+    let public = BigUint::from(2u8).modpow(&private, &prime);
+
+    // The shared secret is padded to the length of the prime before deriving the key.
+    let shared = Zeroizing::new(client_public.modpow(&private, &prime).to_bytes_be());
+    let mut ikm = Zeroizing::new([0; 128]);
+    ikm[128 - shared.len()..].copy_from_slice(&shared);
+
+    let mut key = Zeroizing::new([0; 16]);
+    Hkdf::<Sha256>::new(None, ikm.as_ref())
+        .expand(&[], key.as_mut())
+        .expect("16 bytes is a valid output length");
+
+    Ok((public.to_bytes_be(), key))
+}
+
+struct Session {
+    state: Arc<State>,
+}
+#[interface(name = "org.freedesktop.Secret.Session")]
+impl Session {
+    async fn close(
+        &self,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> Result<()> {
+        if let Some(path) = header.path() {
+            let path = OwnedObjectPath::from(path.clone());
+            self.state.sessions.lock().unwrap().remove(&path);
+            server.remove::<Self, _>(&path).await?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Collection {
+    account: String,
+    state: Arc<State>,
+}
+#[interface(name = "org.freedesktop.Secret.Collection")]
+impl Collection {
+    fn delete(&self) -> Result<OwnedObjectPath> {
+        self.state.check_writable()?;
+        accounts_only()
+    }
+
+    async fn search_items(
+        &self,
+        attributes: HashMap<String, String>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> Result<Vec<OwnedObjectPath>> {
+        refresh(server, &self.state).await?;
+
+        Ok(search(&self.state, &self.account, &attributes)?)
+    }
+
+    /// Store a new login, or with `replace`, change the secret and label of the first item
+    /// whose attributes match.
+    async fn create_item(
+        &self,
+        properties: HashMap<String, OwnedValue>,
+        secret: Secret,
+        replace: bool,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> Result<(OwnedObjectPath, OwnedObjectPath)> {
+        self.state.check_writable()?;
+        let label = match properties.get(LABEL) {
+            Some(label) => String::try_from(label.try_clone()?)?,
+            None => String::new(),
+        };
+        let attributes = match properties.get(ATTRIBUTES) {
+            Some(attributes) => HashMap::<String, String>::try_from(attributes.try_clone()?)?,
+            None => HashMap::new(),
+        };
+        let secret = self.state.decode_secret(&secret)?;
+
+        let existing = if replace {
+            search(&self.state, &self.account, &attributes)?
+                .into_iter()
+                .next()
+        } else {
+            None
+        };
+        let path = match existing {
+            Some(path) => {
+                let cipher = cipher_at(&self.account, &path)?
+                    .ok_or_else(|| Error::NoSuchObject(format!("No item at {}", path.as_str())))?;
+                update(&self.state, &self.account, &cipher, |cipher| {
+                    cipher.name = label;
+                    set_secret(cipher, &secret);
+                })?;
+                path
+            }
+            None => {
+                let id = create(&self.state, &self.account, label, &attributes, &secret)?;
+                item_path(&self.account, id)
+            }
+        };
+        refresh(server, &self.state).await?;
+
+        Ok((path, ObjectPath::from_static_str_unchecked("/").into()))
+    }
+
+    #[zbus(property)]
+    fn items(&self) -> zbus::fdo::Result<Vec<OwnedObjectPath>> {
+        Ok(ciphers(&self.account)
+            .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?
+            .iter()
+            .filter_map(cipher_id)
+            .map(|id| item_path(&self.account, id))
+            .collect())
+    }
+    #[zbus(property)]
+    fn label(&self) -> String {
+        self.account.clone()
+    }
+    #[zbus(property)]
+    fn locked(&self) -> bool {
+        !self.state.is_unlocked(&self.account)
+    }
+    #[zbus(property)]
+    fn created(&self) -> u64 {
+        0
+    }
+    #[zbus(property)]
+    fn modified(&self) -> u64 {
+        0
+    }
+}
+
+struct Item {
+    account: String,
+    id: Uuid,
+    state: Arc<State>,
+}
+impl Item {
+    fn cipher(&self) -> Result<Value> {
+        ciphers(&self.account)?
+            .into_iter()
+            .find(|cipher| cipher_id(cipher) == Some(self.id))
+            .ok_or_else(|| Error::NoSuchObject(format!("Item {} no longer exists", self.id)))
+    }
+    fn decrypt(&self) -> Result<Decrypted> {
+        decrypt(&self.state, &self.account, &self.cipher()?)?
+            .ok_or_else(|| Error::IsLocked(format!("{} is locked", self.account)))
+    }
+}
+#[interface(name = "org.freedesktop.Secret.Item")]
+impl Item {
+    /// Move the item to the trash. The object itself is taken down by the next refresh, since
+    /// it can't be removed while handling its own call.
+    fn delete(&self) -> Result<OwnedObjectPath> {
+        self.state.check_writable()?;
+        // Only unlocked items can be deleted, like any other change.
+        self.decrypt()?;
+        delete(&self.state, &self.account, self.id)?;
+
+        Ok(ObjectPath::from_static_str_unchecked("/").into())
+    }
+
+    fn get_secret(&self, session: ObjectPath<'_>) -> Result<Secret> {
+        let item = self.decrypt()?;
+        self.state.keys.lock().unwrap().touch(&self.account);
+
+        self.state.encode_secret(&session, item.secret.as_bytes())
+    }
+
+    fn set_secret(&self, secret: Secret) -> Result<()> {
+        self.state.check_writable()?;
+        let secret = self.state.decode_secret(&secret)?;
+
+        update(&self.state, &self.account, &self.cipher()?, |cipher| {
+            set_secret(cipher, &secret)
+        })
+    }
+
+    #[zbus(property)]
+    fn locked(&self) -> bool {
+        !self.state.is_unlocked(&self.account)
+    }
+    /// Empty while locked, since the attributes are encrypted.
+    #[zbus(property)]
+    fn attributes(&self) -> HashMap<String, String> {
+        self.decrypt()
+            .map(|item| item.attributes)
+            .unwrap_or_default()
+    }
+    #[zbus(property)]
+    fn label(&self) -> String {
+        self.decrypt().map(|item| item.label).unwrap_or_default()
+    }
+    #[zbus(property)]
+    fn created(&self) -> u64 {
+        self.cipher()
+            .map_or(0, |cipher| timestamp(&cipher["creationDate"]))
+    }
+    #[zbus(property)]
+    fn modified(&self) -> u64 {
+        self.cipher()
+            .map_or(0, |cipher| timestamp(&cipher["revisionDate"]))
+    }
+}
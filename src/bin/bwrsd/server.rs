@@ -0,0 +1,140 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use bwrs::{
+    agent::{read_message, socket_path, write_message, Request, Response},
+    crypto::SymmetricKey,
+    storage::Storage,
+};
+use eyre::{eyre, Result, WrapErr};
+use zeroize::Zeroizing;
+
+use crate::{
+    events::Subscribers,
+    keys::{maximum_vault_timeout, KeyStore},
+};
+
+/// Listen on the agent socket, answering each client on its own thread.
+pub fn run(keys: Arc<Mutex<KeyStore>>, subscribers: Arc<Subscribers>) -> Result<()> {
+    let listener = bind(&socket_path()?)?;
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let keys = Arc::clone(&keys);
+        let subscribers = Arc::clone(&subscribers);
+
+        thread::spawn(move || {
+            if let Err(e) = handle_client(stream, &keys, &subscribers) {
+                eprintln!("Client error: {e}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Listen on a socket only the current user can connect to, replacing a stale one.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    if let Some(dir) = path.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+    }
+
+    if UnixStream::connect(path).is_ok() {
+        return Err(eyre!("bwrsd is already listening on {}", path.display()));
+    }
+    // Nothing answered, so any existing socket was left behind by an agent which died.
+    let _ = fs::remove_file(path);
+
+    let listener = UnixListener::bind(path)
+        .wrap_err_with(|| format!("Failed to listen on {}", path.display()))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+    Ok(listener)
+}
+
+fn handle_client(
+    mut stream: UnixStream,
+    keys: &Mutex<KeyStore>,
+    subscribers: &Subscribers,
+) -> Result<()> {
+    while let Some(request) = read_message::<Request>(&mut stream)? {
+        if let Request::Subscribe = request {
+            let events = subscribers.add();
+            write_message(&mut stream, &Response::Ok)?;
+
+            // From here on the connection only carries events, until the client goes away.
+            for event in events {
+                write_message(&mut stream, &Response::Event(event))?;
+            }
+
+            return Ok(());
+        }
+
+        let response = respond(request, keys).unwrap_or_else(|e| Response::Error {
+            message: e.to_string(),
+        });
+        write_message(&mut stream, &response)?;
+    }
+
+    Ok(())
+}
+
+fn respond(request: Request, keys: &Mutex<KeyStore>) -> Result<Response> {
+    Ok(match request {
+        Request::Ping => Response::Pong,
+        Request::Unlock {
+            account,
+            user_key,
+            org_keys,
+        } => {
+            let user_key = SymmetricKey::from_base64(&user_key)?;
+            let org_keys = org_keys
+                .into_iter()
+                .map(|(id, key)| Ok((id, SymmetricKey::from_base64(&key)?)))
+                .collect::<Result<BTreeMap<_, _>>>()?;
+
+            let max_timeout = maximum_vault_timeout(&Storage::open()?.profile(&account)?)?;
+
+            keys.lock()
+                .unwrap()
+                .unlock(account, user_key, org_keys, max_timeout);
+
+            Response::Ok
+        }
+        Request::GetKeys { account } => match keys.lock().unwrap().get(&account) {
+            Some(unlocked) => Response::Keys {
+                user_key: Zeroizing::new(unlocked.user_key.key().to_base64()),
+                org_keys: unlocked
+                    .org_keys
+                    .iter()
+                    .map(|(id, key)| (*id, Zeroizing::new(key.key().to_base64())))
+                    .collect(),
+            },
+            None => Response::Locked,
+        },
+        Request::HasKeys { account } => match keys.lock().unwrap().peek(&account) {
+            Some(_) => Response::Ok,
+            None => Response::Locked,
+        },
+        Request::Lock { account } => {
+            for account in keys.lock().unwrap().lock(account.as_deref()) {
+                eprintln!("Locked {account}: requested by client");
+            }
+
+            Response::Ok
+        }
+        Request::Subscribe => unreachable!("handled by handle_client"),
+    })
+}
@@ -0,0 +1,282 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    process::Command,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use bwrs::{
+    storage::Storage,
+    vault::{Cipher, CipherKind},
+};
+use eyre::{eyre, OptionExt, Result};
+use rsa::signature::{SignatureEncoding, Signer};
+use serde_json::Value;
+use sha2::{Sha256, Sha512};
+use ssh_key::{private::KeypairData, Algorithm, HashAlg, PrivateKey, Signature};
+
+use crate::{keys::KeyStore, server};
+
+/// Requests larger than this are rejected. OpenSSH uses the same limit.
+const MAX_MESSAGE_LEN: u32 = 256 * 1024;
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+/// The vault's SSH key cipher type.
+const CIPHER_SSH_KEY: u64 = 5;
+
+/// An SSH key from an unlocked account's vault.
+struct Identity {
+    account: String,
+    name: String,
+    key: PrivateKey,
+}
+
+/// Serve the SSH agent protocol on `path`, offering the SSH keys stored in every unlocked
+/// account. If `confirm` is set, it's run with `sh -c` before each signature, which is only
+/// made if it exits successfully.
+pub fn spawn(path: &Path, keys: Arc<Mutex<KeyStore>>, confirm: Option<String>) -> Result<()> {
+    let listener = server::bind(path)?;
+    eprintln!("Serving SSH keys on SSH_AUTH_SOCK={}", path.display());
+
+    let confirm = Arc::new(confirm);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let keys = Arc::clone(&keys);
+            let confirm = Arc::clone(&confirm);
+
+            thread::spawn(move || {
+                if let Err(e) = handle_client(stream, &keys, confirm.as_deref()) {
+                    eprintln!("SSH client error: {e}");
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_client(
+    mut stream: UnixStream,
+    keys: &Mutex<KeyStore>,
+    confirm: Option<&str>,
+) -> Result<()> {
+    while let Some(request) = read_message(&mut stream)? {
+        let response = respond(&request, keys, confirm).unwrap_or_else(|e| {
+            eprintln!("SSH request failed: {e}");
+            vec![SSH_AGENT_FAILURE]
+        });
+
+        stream.write_all(&(response.len() as u32).to_be_bytes())?;
+        stream.write_all(&response)?;
+    }
+
+    Ok(())
+}
+
+fn respond(request: &[u8], keys: &Mutex<KeyStore>, confirm: Option<&str>) -> Result<Vec<u8>> {
+    let (&kind, mut body) = request.split_first().ok_or_eyre("Empty request")?;
+
+    match kind {
+        SSH_AGENTC_REQUEST_IDENTITIES => {
+            let identities = identities(keys)?;
+
+            let mut response = vec![SSH_AGENT_IDENTITIES_ANSWER];
+            response.extend((identities.len() as u32).to_be_bytes());
+            for identity in identities {
+                write_string(&mut response, &identity.key.public_key().to_bytes()?);
+                write_string(&mut response, identity.name.as_bytes());
+            }
+
+            Ok(response)
+        }
+        SSH_AGENTC_SIGN_REQUEST => {
+            let blob = read_string(&mut body)?;
+            let data = read_string(&mut body)?;
+            let flags = read_u32(&mut body)?;
+
+            let identity = identities(keys)?
+                .into_iter()
+                .find(|identity| {
+                    identity
+                        .key
+                        .public_key()
+                        .to_bytes()
+                        .is_ok_and(|b| b == blob)
+                })
+                .ok_or_eyre("No unlocked vault holds the requested key")?;
+
+            if let Some(command) = confirm {
+                let status = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .env("BWRS_ACCOUNT", &identity.account)
+                    .env("BWRS_SSH_KEY_NAME", &identity.name)
+                    .env(
+                        "BWRS_SSH_KEY_FINGERPRINT",
+                        identity.key.fingerprint(HashAlg::Sha256).to_string(),
+                    )
+                    .status()?;
+                if !status.success() {
+                    return Err(eyre!("Use of \"{}\" was not confirmed", identity.name));
+                }
+            }
+
+            let signature = sign(&identity.key, data, flags)?;
+            // Only signing counts as activity, not the listing every connection starts with.
+            keys.lock().unwrap().touch(&identity.account);
+            let mut encoded = Vec::new();
+            write_string(&mut encoded, signature.algorithm().as_str().as_bytes());
+            write_string(&mut encoded, signature.as_bytes());
+
+            let mut response = vec![SSH_AGENT_SIGN_RESPONSE];
+            write_string(&mut response, &encoded);
+
+            Ok(response)
+        }
+        // Keys live in the vault, so adding, removing and locking through the agent isn't
+        // supported.
+        _ => Ok(vec![SSH_AGENT_FAILURE]),
+    }
+}
+
+fn sign(key: &PrivateKey, data: &[u8], flags: u32) -> Result<Signature> {
+    let KeypairData::Rsa(keypair) = key.key_data() else {
+        return Ok(key.try_sign(data)?);
+    };
+
+    // ssh-key's own conversion passes `p` twice, which rsa rejects, so build the key here.
+    let [n, e, d, p, q] = [
+        &keypair.public.n,
+        &keypair.public.e,
+        &keypair.private.d,
+        &keypair.private.p,
+        &keypair.private.q,
+    ]
+    .map(rsa::BigUint::try_from);
+    let private_key = rsa::RsaPrivateKey::from_components(n?, e?, d?, vec![p?, q?])?;
+
+    // RSA keys sign with whichever hash the client asked for. SHA-1 signatures aren't offered.
+    let (hash, signature) = if flags & SSH_AGENT_RSA_SHA2_512 != 0 {
+        let signing_key = rsa::pkcs1v15::SigningKey::<Sha512>::new(private_key);
+        (HashAlg::Sha512, signing_key.try_sign(data)?.to_vec())
+    } else if flags & SSH_AGENT_RSA_SHA2_256 != 0 {
+        let signing_key = rsa::pkcs1v15::SigningKey::<Sha256>::new(private_key);
+        (HashAlg::Sha256, signing_key.try_sign(data)?.to_vec())
+    } else {
+        return Err(eyre!("ssh-rsa (SHA-1) signatures are not supported"));
+    };
+
+    Ok(Signature::new(
+        Algorithm::Rsa { hash: Some(hash) },
+        signature,
+    )?)
+}
+
+/// Decrypt the SSH keys in every unlocked account's vault cache. Keys which can't be decrypted
+/// or parsed are skipped.
+fn identities(keys: &Mutex<KeyStore>) -> Result<Vec<Identity>> {
+    let storage = Storage::open()?;
+    let mut identities = Vec::new();
+
+    let accounts = keys.lock().unwrap().accounts();
+    for account in accounts {
+        let Some(vault) = storage.profile(&account)?.vault()? else {
+            continue;
+        };
+        let ciphers = vault["ciphers"].as_array().cloned().unwrap_or_default();
+
+        for cipher in ciphers {
+            if cipher["type"].as_u64() != Some(CIPHER_SSH_KEY) || !cipher["deletedDate"].is_null() {
+                continue;
+            }
+
+            match decrypt_identity(keys, &account, &cipher) {
+                Ok(Some(identity)) => identities.push(identity),
+                Ok(None) => {}
+                Err(e) => eprintln!("Skipping SSH key {} in {account}: {e}", cipher["id"]),
+            }
+        }
+    }
+
+    Ok(identities)
+}
+
+/// Decrypt one SSH key cipher, or `None` if the account was locked in the meantime.
+fn decrypt_identity(
+    keys: &Mutex<KeyStore>,
+    account: &str,
+    cipher: &Value,
+) -> Result<Option<Identity>> {
+    let organization_id = match cipher["organizationId"].as_str() {
+        Some(id) => Some(id.parse()?),
+        None => None,
+    };
+    let Some(key) = keys.lock().unwrap().cipher_key(account, organization_id)? else {
+        return Ok(None);
+    };
+    let cipher = Cipher::decrypt(cipher, &key)?;
+    let CipherKind::SshKey(ssh_key) = &cipher.kind else {
+        return Err(eyre!("Not an SSH key"));
+    };
+
+    Ok(Some(Identity {
+        account: account.to_string(),
+        key: PrivateKey::from_openssh(ssh_key.private_key.as_bytes())?,
+        name: cipher.name,
+    }))
+}
+
+/// Read a length-prefixed request, returning `None` if the client closed the connection.
+fn read_message(stream: &mut impl Read) -> Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match stream.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    let len = u32::from_be_bytes(len);
+    if len > MAX_MESSAGE_LEN {
+        return Err(eyre!("Request of {len} bytes is too large"));
+    }
+
+    let mut body = vec![0; len as usize];
+    stream.read_exact(&mut body)?;
+
+    Ok(Some(body))
+}
+
+fn read_u32(input: &mut &[u8]) -> Result<u32> {
+    if input.len() < 4 {
+        return Err(eyre!("Truncated request"));
+    }
+    let (value, rest) = input.split_at(4);
+    *input = rest;
+
+    Ok(u32::from_be_bytes(value.try_into()?))
+}
+fn read_string<'a>(input: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = read_u32(input)? as usize;
+    if input.len() < len {
+        return Err(eyre!("Truncated request"));
+    }
+    let (value, rest) = input.split_at(len);
+    *input = rest;
+
+    Ok(value)
+}
+fn write_string(output: &mut Vec<u8>, value: &[u8]) {
+    output.extend((value.len() as u32).to_be_bytes());
+    output.extend(value);
+}
@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bwrs::{
+    agent::Event,
+    storage::{Profile, Storage},
+};
+use eyre::Result;
+use rand::Rng;
+
+use crate::{events::Subscribers, keys::KeyStore};
+
+const TICK: Duration = Duration::from_secs(1);
+/// The first retry after a failed sync, doubled on each further failure.
+const MIN_BACKOFF: Duration = Duration::from_secs(15);
+/// Retries never wait longer than this, even if the sync interval is longer.
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+struct Schedule {
+    next: Instant,
+    failures: u32,
+}
+
+/// Periodically sync every unlocked account, so the next `bwrs` command reads fresh data from
+/// the cache. Subscribed programs are told when a vault changes. Accounts are synced as soon as
+/// they're unlocked, then every `interval`.
+pub fn spawn(keys: Arc<Mutex<KeyStore>>, subscribers: Arc<Subscribers>, interval: Duration) {
+    thread::spawn(move || {
+        let mut schedules = HashMap::<String, Schedule>::new();
+
+        loop {
+            thread::sleep(TICK);
+
+            let accounts = keys.lock().unwrap().accounts();
+            schedules.retain(|account, _| accounts.contains(account));
+
+            for account in accounts {
+                let now = Instant::now();
+                let schedule = schedules.entry(account.clone()).or_insert(Schedule {
+                    next: now,
+                    failures: 0,
+                });
+                if now < schedule.next {
+                    continue;
+                }
+
+                match Storage::open()
+                    .and_then(|storage| storage.profile(&account))
+                    .and_then(|profile| sync(&keys, &account, &profile))
+                {
+                    Ok(changed) => {
+                        schedule.failures = 0;
+                        schedule.next = now + jitter(interval);
+
+                        if changed {
+                            subscribers.notify(&Event::VaultChanged { account });
+                        }
+                    }
+                    Err(e) => {
+                        let backoff = MIN_BACKOFF
+                            .saturating_mul(2u32.saturating_pow(schedule.failures))
+                            .min(MAX_BACKOFF.max(interval));
+                        schedule.failures += 1;
+                        schedule.next = now + jitter(backoff);
+
+                        eprintln!(
+                            "Failed to sync {account}, retrying in {}s: {e}",
+                            backoff.as_secs()
+                        );
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Sync an account, then pick up any organizations it joined and their policies.
+fn sync(keys: &Mutex<KeyStore>, account: &str, profile: &Profile) -> Result<bool> {
+    let changed = profile.sync()?;
+
+    if changed {
+        keys.lock().unwrap().refresh(account, profile)?;
+    }
+
+    Ok(changed)
+}
+
+/// Spread syncs by up to 10% either way, so many agents don't hit the server in lockstep.
+fn jitter(duration: Duration) -> Duration {
+    duration.mul_f64(rand::thread_rng().gen_range(0.9..1.1))
+}
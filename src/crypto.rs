@@ -1,54 +1,237 @@
+use std::{fmt, str::FromStr, sync::OnceLock};
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 use argon2::{
     Algorithm as Argon2Algorithm, Argon2, Params as Argon2Params, Version as Argon2Version,
 };
 use base64::Engine;
-use eyre::Result;
+use eyre::{eyre, OptionExt, Result};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
 use pbkdf2::{pbkdf2_hmac, pbkdf2_hmac_array};
+use rand::{rngs::OsRng, RngCore};
 use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
 
 use crate::api::{KdfConfig, KdfKind};
 
-struct SymmetricKey([u8; 64]);
-
-// AKA makeMasterKey
-fn deriveKeyFromPassword(
-    password: String,
-    salt: String,
-    kdf_config: KdfConfig,
-) -> Result<SymmetricKey> {
-    let mut final_key = [0; 64];
-    let enc_key = &mut final_key.as_mut_slice()[0..32];
-
-    match kdf_config.kind {
-        KdfKind::Pbkdf2 => pbkdf2_hmac::<Sha256>(
-            password.as_bytes(),
-            salt.as_bytes(),
-            kdf_config.iterations,
-            enc_key,
-        ),
-        KdfKind::Argon2id => {
-            let hash = Sha256::digest(salt);
-            Argon2::new(
-                Argon2Algorithm::Argon2id,
-                Argon2Version::V0x13,
-                Argon2Params::new(
-                    kdf_config.memory.unwrap() * 1024,
-                    kdf_config.iterations,
-                    kdf_config.parallelism.unwrap(),
-                    Some(32),
+/// A key derived from the user's master password, used to authenticate and to unwrap the user key.
+pub struct MasterKey([u8; 32]);
+impl MasterKey {
+    // AKA makeMasterKey
+    pub fn derive(password: &str, email: &str, kdf_config: &KdfConfig) -> Result<Self> {
+        let mut key = [0; 32];
+        let salt = email.trim().to_lowercase();
+
+        match kdf_config.kind {
+            KdfKind::Pbkdf2 => pbkdf2_hmac::<Sha256>(
+                password.as_bytes(),
+                salt.as_bytes(),
+                kdf_config.iterations,
+                &mut key,
+            ),
+            KdfKind::Argon2id => {
+                let hash = Sha256::digest(salt.as_bytes());
+                Argon2::new(
+                    Argon2Algorithm::Argon2id,
+                    Argon2Version::V0x13,
+                    Argon2Params::new(
+                        kdf_config.memory.ok_or_eyre("Missing Argon2 memory")? * 1024,
+                        kdf_config.iterations,
+                        kdf_config
+                            .parallelism
+                            .ok_or_eyre("Missing Argon2 parallelism")?,
+                        Some(32),
+                    )
+                    .map_err(|e| eyre!("Invalid Argon2 parameters: {e}"))?,
                 )
-                .unwrap(),
-            )
-            .hash_password_into(password.as_bytes(), &hash, enc_key)
-            .unwrap();
+                .hash_password_into(password.as_bytes(), &hash, &mut key)
+                .map_err(|e| eyre!("Failed to derive master key: {e}"))?;
+            }
+        };
+
+        Ok(Self(key))
+    }
+
+    #[must_use]
+    pub fn hash(&self, password: &str, purpose: HashPurpose) -> [u8; 32] {
+        pbkdf2_hmac_array::<Sha256, 32>(&self.0, password.as_bytes(), purpose.iterations())
+    }
+
+    /// Expand the master key into an encryption and MAC key pair.
+    #[must_use]
+    pub fn stretch(&self) -> SymmetricKey {
+        let hkdf = Hkdf::<Sha256>::from_prk(&self.0).unwrap();
+
+        let mut key = [0; 64];
+        hkdf.expand(b"enc", &mut key[..32]).unwrap();
+        hkdf.expand(b"mac", &mut key[32..]).unwrap();
+
+        SymmetricKey(key)
+    }
+}
+impl Drop for MasterKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// A 64 byte key, made up of a 32 byte encryption key followed by a 32 byte MAC key.
+pub struct SymmetricKey([u8; 64]);
+impl SymmetricKey {
+    #[must_use]
+    pub fn generate() -> Self {
+        let mut key = [0; 64];
+        OsRng.fill_bytes(&mut key);
+
+        Self(key)
+    }
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        Ok(Self(bytes.try_into().map_err(|_| {
+            eyre!("Expected a 64 byte key, got {} bytes", bytes.len())
+        })?))
+    }
+    pub fn from_base64(input: &str) -> Result<Self> {
+        let mut bytes = Base64::decode(input)?;
+        let key = Self::from_slice(&bytes);
+        bytes.zeroize();
+
+        key
+    }
+    #[must_use]
+    pub fn to_base64(&self) -> String {
+        Base64::encode(self.0)
+    }
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8; 64] {
+        &self.0
+    }
+
+    fn enc_key(&self) -> &[u8] {
+        &self.0[..32]
+    }
+    fn mac_key(&self) -> &[u8] {
+        &self.0[32..]
+    }
+    fn mac(&self, iv: &[u8], data: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.mac_key()).unwrap();
+        mac.update(iv);
+        mac.update(data);
+
+        mac
+    }
+
+    #[must_use]
+    pub fn encrypt(&self, plaintext: &[u8]) -> EncString {
+        let mut iv = [0; 16];
+        OsRng.fill_bytes(&mut iv);
+
+        let data = cbc::Encryptor::<aes::Aes256>::new_from_slices(self.enc_key(), &iv)
+            .unwrap()
+            .encrypt_padded_vec_mut::<Pkcs7>(plaintext);
+        let mac = self.mac(&iv, &data).finalize().into_bytes().into();
+
+        EncString::AesCbc256HmacSha256 { iv, data, mac }
+    }
+    pub fn decrypt(&self, enc: &EncString) -> Result<Vec<u8>> {
+        let (iv, data) = match enc {
+            EncString::AesCbc256 { iv, data } => (iv, data),
+            EncString::AesCbc256HmacSha256 { iv, data, mac } => {
+                self.mac(iv, data)
+                    .verify_slice(mac)
+                    .map_err(|_| eyre!("MAC verification failed"))?;
+
+                (iv, data)
+            }
+            EncString::Rsa2048OaepSha1 { .. } => {
+                return Err(eyre!(
+                    "Cannot decrypt an RSA encrypted value with a symmetric key"
+                ))
+            }
+        };
+
+        cbc::Decryptor::<aes::Aes256>::new_from_slices(self.enc_key(), iv)
+            .unwrap()
+            .decrypt_padded_vec_mut::<Pkcs7>(data)
+            .map_err(|_| eyre!("Failed to decrypt value"))
+    }
+    pub fn decrypt_str(&self, enc: &EncString) -> Result<String> {
+        Ok(String::from_utf8(self.decrypt(enc)?)?)
+    }
+}
+impl Drop for SymmetricKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// An account's RSA private key, which unwraps keys shared with the account, such as the keys of
+/// the organizations it belongs to.
+pub struct PrivateKey(rsa::RsaPrivateKey);
+impl PrivateKey {
+    /// Parse a PKCS#8 DER encoded key, as stored (encrypted) in the account's `PrivateKey`.
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        use rsa::pkcs8::DecodePrivateKey;
+
+        Ok(Self(
+            rsa::RsaPrivateKey::from_pkcs8_der(der)
+                .map_err(|e| eyre!("Invalid private key: {e}"))?,
+        ))
+    }
+
+    /// The account's public key, which the server hands out to other users.
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.0.to_public_key())
+    }
+
+    pub fn decrypt(&self, enc: &EncString) -> Result<Vec<u8>> {
+        match enc {
+            EncString::Rsa2048OaepSha1 { data } => self
+                .0
+                .decrypt(rsa::Oaep::new::<sha1::Sha1>(), data)
+                .map_err(|_| eyre!("Failed to decrypt value")),
+            _ => Err(eyre!(
+                "Cannot decrypt a symmetrically encrypted value with a private key"
+            )),
         }
-    };
+    }
+}
+
+/// An account's RSA public key, which wraps keys shared with the account.
+pub struct PublicKey(rsa::RsaPublicKey);
+impl PublicKey {
+    /// Parse an SPKI DER encoded key, as the server returns for each user.
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        use rsa::pkcs8::DecodePublicKey;
+
+        Ok(Self(
+            rsa::RsaPublicKey::from_public_key_der(der)
+                .map_err(|e| eyre!("Invalid public key: {e}"))?,
+        ))
+    }
+    pub fn to_der(&self) -> Result<Vec<u8>> {
+        use rsa::pkcs8::EncodePublicKey;
 
-    Ok(SymmetricKey(final_key))
+        Ok(self
+            .0
+            .to_public_key_der()
+            .map_err(|e| eyre!("Invalid public key: {e}"))?
+            .into_vec())
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<EncString> {
+        let data = self
+            .0
+            .encrypt(&mut OsRng, rsa::Oaep::new::<sha1::Sha1>(), plaintext)
+            .map_err(|e| eyre!("Failed to encrypt value: {e}"))?;
+
+        Ok(EncString::Rsa2048OaepSha1 { data })
+    }
 }
 
+#[derive(Clone, Copy)]
 #[repr(u8)]
-enum HashPurpose {
+pub enum HashPurpose {
     ServerAuthorization = 1,
     LocalAuthorization = 2,
 }
@@ -57,8 +240,145 @@ impl HashPurpose {
         self as u32
     }
 }
-fn hashMasterKey(password: &[u8], key: &[u8], purpose: HashPurpose) -> [u8; 32] {
-    pbkdf2_hmac_array::<Sha256, 32>(password, key, purpose.iterations())
+
+/// An encrypted value, in the `<type>.<iv>|<data>|<mac>` format used throughout the Bitwarden API.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EncString {
+    AesCbc256 {
+        iv: [u8; 16],
+        data: Vec<u8>,
+    },
+    AesCbc256HmacSha256 {
+        iv: [u8; 16],
+        data: Vec<u8>,
+        mac: [u8; 32],
+    },
+    Rsa2048OaepSha1 {
+        data: Vec<u8>,
+    },
+}
+impl FromStr for EncString {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, rest) = s.split_once('.').ok_or_eyre("Invalid encrypted string")?;
+        let parts = rest
+            .split('|')
+            .map(Base64::decode)
+            .collect::<Result<Vec<_>>>()?;
+        let iv = |part: &[u8]| -> Result<[u8; 16]> {
+            part.try_into().map_err(|_| eyre!("Invalid IV length"))
+        };
+
+        Ok(match (kind, parts.as_slice()) {
+            ("0", [iv_part, data]) => Self::AesCbc256 {
+                iv: iv(iv_part)?,
+                data: data.clone(),
+            },
+            ("2", [iv_part, data, mac]) => Self::AesCbc256HmacSha256 {
+                iv: iv(iv_part)?,
+                data: data.clone(),
+                mac: mac
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| eyre!("Invalid MAC length"))?,
+            },
+            ("4", [data]) => Self::Rsa2048OaepSha1 { data: data.clone() },
+            (kind, _) => return Err(eyre!("Unsupported encrypted string type: {kind}")),
+        })
+    }
+}
+impl EncString {
+    /// Parse the binary layout used for attachments: the type byte, then the IV, MAC and data.
+    pub fn from_buffer(buffer: &[u8]) -> Result<Self> {
+        let (&kind, rest) = buffer.split_first().ok_or_eyre("Empty encrypted buffer")?;
+
+        match kind {
+            0 if rest.len() > 16 => Ok(Self::AesCbc256 {
+                iv: rest[..16].try_into()?,
+                data: rest[16..].to_vec(),
+            }),
+            2 if rest.len() > 48 => Ok(Self::AesCbc256HmacSha256 {
+                iv: rest[..16].try_into()?,
+                mac: rest[16..48].try_into()?,
+                data: rest[48..].to_vec(),
+            }),
+            0 | 2 => Err(eyre!("Truncated encrypted buffer")),
+            kind => Err(eyre!("Unsupported encrypted buffer type: {kind}")),
+        }
+    }
+    /// Lay the value out in the binary form used for attachments.
+    pub fn to_buffer(&self) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::AesCbc256 { iv, data } => [&[0][..], iv, data].concat(),
+            Self::AesCbc256HmacSha256 { iv, data, mac } => [&[2][..], iv, mac, data].concat(),
+            Self::Rsa2048OaepSha1 { .. } => {
+                return Err(eyre!("RSA encrypted values have no buffer form"))
+            }
+        })
+    }
+}
+impl fmt::Display for EncString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AesCbc256 { iv, data } => {
+                write!(f, "0.{}|{}", Base64::encode(iv), Base64::encode(data))
+            }
+            Self::AesCbc256HmacSha256 { iv, data, mac } => write!(
+                f,
+                "2.{}|{}|{}",
+                Base64::encode(iv),
+                Base64::encode(data),
+                Base64::encode(mac)
+            ),
+            Self::Rsa2048OaepSha1 { data } => write!(f, "4.{}", Base64::encode(data)),
+        }
+    }
+}
+
+/// The EFF's long wordlist, of 7776 words which are easy to tell apart.
+pub fn wordlist() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&str>> = OnceLock::new();
+
+    WORDS.get_or_init(|| {
+        include_str!("eff_large_wordlist.txt")
+            .lines()
+            .filter_map(|line| line.split('\t').nth(1))
+            .collect()
+    })
+}
+
+/// Bitwarden's fingerprint phrase for a public key, which lets two users check over another
+/// channel that the server handed out the right key. `material` is usually the key owner's user
+/// id. The key's SHA-256 hash is expanded with HKDF over `material`, and the result is read as a
+/// number in base 7776 with one word per digit, enough for 64 bits of entropy.
+pub fn fingerprint(material: &str, public_key: &[u8]) -> String {
+    const WORDS: usize = 5;
+
+    let hash = Sha256::digest(public_key);
+    let mut number = [0; 32];
+    Hkdf::<Sha256>::from_prk(&hash)
+        .unwrap()
+        .expand(material.as_bytes(), &mut number)
+        .unwrap();
+
+    let words = wordlist();
+    (0..WORDS)
+        .map(|_| words[divide(&mut number, words.len() as u32) as usize])
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Divide a big-endian number in place, returning the remainder.
+fn divide(number: &mut [u8], divisor: u32) -> u32 {
+    let mut remainder = 0;
+    for digit in number {
+        let value = (remainder << 8) | u32::from(*digit);
+        *digit = (value / divisor) as u8;
+        remainder = value % divisor;
+    }
+
+    remainder
 }
 
 pub struct Base64;
@@ -69,4 +389,44 @@ impl Base64 {
     pub fn encode<T: AsRef<[u8]>>(input: T) -> String {
         base64::engine::general_purpose::STANDARD.encode(input)
     }
+    pub fn decode<T: AsRef<[u8]>>(input: T) -> Result<Vec<u8>> {
+        Ok(base64::engine::general_purpose::STANDARD.decode(input)?)
+    }
+    pub fn decode_url_safe<T: AsRef<[u8]>>(input: T) -> Result<Vec<u8>> {
+        Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(input)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_matches_the_official_clients() {
+        // The user id and DER public key from the Bitwarden SDK's own fingerprint test.
+        let user_id = "a09726a0-9590-49d1-a5f5-afe300b6a515";
+        let public_key: &[u8] = &[
+            48, 130, 1, 34, 48, 13, 6, 9, 42, 134, 72, 134, 247, 13, 1, 1, 1, 5, 0, 3, 130, 1, 15,
+            0, 48, 130, 1, 10, 2, 130, 1, 1, 0, 187, 38, 44, 241, 110, 205, 89, 253, 25, 191, 126,
+            84, 121, 202, 61, 223, 189, 244, 118, 212, 74, 139, 130, 97, 115, 164, 167, 106, 191,
+            188, 233, 218, 196, 250, 187, 146, 125, 160, 150, 49, 198, 224, 176, 10, 0, 143, 99,
+            230, 232, 160, 51, 104, 154, 211, 33, 80, 170, 4, 68, 80, 219, 115, 167, 114, 156, 227,
+            125, 193, 128, 123, 39, 254, 191, 124, 63, 129, 44, 63, 18, 56, 161, 48, 158, 0, 27,
+            146, 2, 99, 136, 75, 21, 135, 6, 118, 12, 26, 251, 184, 172, 249, 53, 78, 210, 46, 143,
+            17, 104, 202, 65, 173, 229, 219, 233, 144, 163, 101, 216, 238, 152, 54, 158, 1, 195,
+            50, 203, 21, 226, 12, 82, 170, 175, 170, 160, 21, 247, 248, 80, 97, 123, 0, 152, 116,
+            229, 126, 221, 199, 155, 194, 192, 51, 207, 177, 240, 160, 84, 241, 41, 88, 176, 53,
+            111, 28, 173, 177, 232, 158, 22, 79, 133, 152, 31, 32, 12, 196, 147, 58, 57, 50, 252,
+            208, 131, 150, 179, 132, 178, 150, 234, 251, 143, 125, 163, 144, 20, 46, 71, 168, 252,
+            164, 86, 120, 124, 56, 252, 206, 210, 236, 212, 139, 127, 189, 236, 40, 46, 2, 238, 13,
+            216, 40, 48, 85, 133, 229, 181, 155, 176, 217, 241, 154, 153, 213, 112, 222, 72, 219,
+            197, 3, 219, 56, 77, 109, 47, 72, 251, 131, 36, 240, 96, 169, 31, 82, 93, 166, 242, 3,
+            33, 213, 2, 3, 1, 0, 1,
+        ];
+
+        assert_eq!(
+            fingerprint(user_id, public_key),
+            "turban-deftly-anime-chatroom-unselfish"
+        );
+    }
 }
@@ -0,0 +1,389 @@
+//! Email aliases from forwarding services, like the web vault's forwarded email username
+//! generator. Each alias forwards mail to the inbox of the account behind the API token.
+
+use std::collections::HashMap;
+
+use eyre::{eyre, Result};
+use reqwest::{
+    blocking::{Client, RequestBuilder},
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+/// A service which makes new email aliases on request.
+pub trait ForwardedEmailProvider {
+    /// The service's name, as shown to users.
+    fn name(&self) -> &'static str;
+    /// Create a new alias, noting the website it's for if known, and return its address.
+    fn generate(&self, website: Option<&str>) -> Result<String>;
+}
+
+/// [SimpleLogin](https://simplelogin.io), or a self-hosted instance of it.
+pub struct SimpleLogin {
+    pub base_url: String,
+    pub api_key: String,
+}
+impl SimpleLogin {
+    pub const DEFAULT_URL: &'static str = "https://app.simplelogin.io";
+
+    #[must_use]
+    pub fn new(api_key: String) -> Self {
+        Self {
+            base_url: Self::DEFAULT_URL.to_string(),
+            api_key,
+        }
+    }
+}
+impl ForwardedEmailProvider for SimpleLogin {
+    fn name(&self) -> &'static str {
+        "SimpleLogin"
+    }
+    fn generate(&self, website: Option<&str>) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Alias {
+            alias: String,
+        }
+
+        let mut request = client()
+            .post(format!("{}/api/alias/random/new", trim(&self.base_url)))
+            .header("Authentication", &self.api_key)
+            .json(&json!({ "note": description(website) }));
+        if let Some(website) = website {
+            request = request.query(&[("hostname", website)]);
+        }
+
+        Ok(send::<Alias>(self, request)?.alias)
+    }
+}
+
+/// [addy.io](https://addy.io), formerly AnonAddy, or a self-hosted instance of it.
+pub struct AddyIo {
+    pub base_url: String,
+    pub api_token: String,
+    /// Which of the account's domains to make the alias at, e.g. `anonaddy.me`.
+    pub domain: String,
+}
+impl AddyIo {
+    pub const DEFAULT_URL: &'static str = "https://app.addy.io";
+
+    #[must_use]
+    pub fn new(api_token: String, domain: String) -> Self {
+        Self {
+            base_url: Self::DEFAULT_URL.to_string(),
+            api_token,
+            domain,
+        }
+    }
+}
+impl ForwardedEmailProvider for AddyIo {
+    fn name(&self) -> &'static str {
+        "addy.io"
+    }
+    fn generate(&self, website: Option<&str>) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Alias {
+            email: String,
+        }
+        #[derive(Deserialize)]
+        struct Created {
+            data: Alias,
+        }
+
+        if self.domain.trim().is_empty() {
+            return Err(eyre!("addy.io needs a domain to make the alias at."));
+        }
+
+        let request = client()
+            .post(format!("{}/api/v1/aliases", trim(&self.base_url)))
+            .bearer_auth(&self.api_token)
+            .header("X-Requested-With", "XMLHttpRequest")
+            .json(&json!({
+                "domain": self.domain.trim(),
+                "description": description(website),
+            }));
+
+        Ok(send::<Created>(self, request)?.data.email)
+    }
+}
+
+/// [Firefox Relay](https://relay.firefox.com).
+pub struct FirefoxRelay {
+    pub base_url: String,
+    pub api_token: String,
+}
+impl FirefoxRelay {
+    pub const DEFAULT_URL: &'static str = "https://relay.firefox.com";
+
+    #[must_use]
+    pub fn new(api_token: String) -> Self {
+        Self {
+            base_url: Self::DEFAULT_URL.to_string(),
+            api_token,
+        }
+    }
+}
+impl ForwardedEmailProvider for FirefoxRelay {
+    fn name(&self) -> &'static str {
+        "Firefox Relay"
+    }
+    fn generate(&self, website: Option<&str>) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Alias {
+            full_address: String,
+        }
+
+        let request = client()
+            .post(format!("{}/api/v1/relayaddresses/", trim(&self.base_url)))
+            .header("Authorization", format!("Token {}", self.api_token))
+            .json(&json!({
+                "enabled": true,
+                "generated_for": website.unwrap_or_default(),
+                "description": description(website),
+            }));
+
+        Ok(send::<Alias>(self, request)?.full_address)
+    }
+}
+
+/// [Fastmail](https://www.fastmail.com) masked email, through its JMAP API.
+pub struct Fastmail {
+    pub base_url: String,
+    pub api_token: String,
+}
+impl Fastmail {
+    pub const DEFAULT_URL: &'static str = "https://api.fastmail.com";
+    const MASKED_EMAIL: &'static str = "https://www.fastmail.com/dev/maskedemail";
+
+    #[must_use]
+    pub fn new(api_token: String) -> Self {
+        Self {
+            base_url: Self::DEFAULT_URL.to_string(),
+            api_token,
+        }
+    }
+}
+impl ForwardedEmailProvider for Fastmail {
+    fn name(&self) -> &'static str {
+        "Fastmail"
+    }
+    fn generate(&self, website: Option<&str>) -> Result<String> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Session {
+            api_url: String,
+            primary_accounts: HashMap<String, String>,
+        }
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Response {
+            method_responses: Vec<(String, Value, String)>,
+        }
+
+        let session: Session = send(
+            self,
+            client()
+                .get(format!("{}/jmap/session", trim(&self.base_url)))
+                .bearer_auth(&self.api_token),
+        )?;
+        let account_id = session
+            .primary_accounts
+            .get(Self::MASKED_EMAIL)
+            .ok_or_else(|| eyre!("This Fastmail API token can't make masked emails."))?;
+
+        let response: Response = send(
+            self,
+            client()
+                .post(&session.api_url)
+                .bearer_auth(&self.api_token)
+                .json(&json!({
+                    "using": ["urn:ietf:params:jmap:core", Self::MASKED_EMAIL],
+                    "methodCalls": [[
+                        "MaskedEmail/set",
+                        {
+                            "accountId": account_id,
+                            "create": {
+                                "new-masked-email": {
+                                    "state": "enabled",
+                                    "description": "",
+                                    "forDomain": website.unwrap_or_default(),
+                                },
+                            },
+                        },
+                        "0",
+                    ]],
+                })),
+        )?;
+
+        // JMAP reports failures as successful responses, with the details inside.
+        let (method, result, _) = response
+            .method_responses
+            .first()
+            .ok_or_else(|| eyre!("Fastmail didn't make a masked email."))?;
+        if method != "MaskedEmail/set" {
+            return Err(eyre!(
+                "Fastmail didn't make a masked email: {}",
+                result["type"].as_str().unwrap_or(method)
+            ));
+        }
+        if let Some(email) = result["created"]["new-masked-email"]["email"].as_str() {
+            return Ok(email.to_string());
+        }
+
+        Err(
+            match result["notCreated"]["new-masked-email"]["description"].as_str() {
+                Some(reason) => eyre!("Fastmail didn't make a masked email: {reason}"),
+                None => eyre!("Fastmail didn't make a masked email."),
+            },
+        )
+    }
+}
+
+/// [DuckDuckGo](https://duckduckgo.com/email) email protection.
+pub struct DuckDuckGo {
+    pub base_url: String,
+    pub api_token: String,
+}
+impl DuckDuckGo {
+    pub const DEFAULT_URL: &'static str = "https://quack.duckduckgo.com";
+
+    #[must_use]
+    pub fn new(api_token: String) -> Self {
+        Self {
+            base_url: Self::DEFAULT_URL.to_string(),
+            api_token,
+        }
+    }
+}
+impl ForwardedEmailProvider for DuckDuckGo {
+    fn name(&self) -> &'static str {
+        "DuckDuckGo"
+    }
+    /// DuckDuckGo doesn't record what its aliases are for, so `website` is unused.
+    fn generate(&self, _website: Option<&str>) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Alias {
+            address: String,
+        }
+
+        let request = client()
+            .post(format!("{}/api/email/addresses", trim(&self.base_url)))
+            .bearer_auth(&self.api_token);
+
+        Ok(format!(
+            "{}@duck.com",
+            send::<Alias>(self, request)?.address
+        ))
+    }
+}
+
+/// [Forward Email](https://forwardemail.net).
+pub struct ForwardEmail {
+    pub base_url: String,
+    pub api_token: String,
+    /// Which of the account's domains to make the alias at.
+    pub domain: String,
+}
+impl ForwardEmail {
+    pub const DEFAULT_URL: &'static str = "https://api.forwardemail.net";
+
+    #[must_use]
+    pub fn new(api_token: String, domain: String) -> Self {
+        Self {
+            base_url: Self::DEFAULT_URL.to_string(),
+            api_token,
+            domain,
+        }
+    }
+}
+impl ForwardedEmailProvider for ForwardEmail {
+    fn name(&self) -> &'static str {
+        "Forward Email"
+    }
+    fn generate(&self, website: Option<&str>) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Domain {
+            name: String,
+        }
+        #[derive(Deserialize)]
+        struct Alias {
+            name: String,
+            domain: Option<Domain>,
+        }
+
+        let domain = self.domain.trim();
+        if domain.is_empty() {
+            return Err(eyre!("Forward Email needs a domain to make the alias at."));
+        }
+
+        let request = client()
+            .post(format!(
+                "{}/v1/domains/{domain}/aliases",
+                trim(&self.base_url)
+            ))
+            // The token is the username, with no password.
+            .basic_auth(&self.api_token, None::<&str>)
+            .json(&json!({
+                "labels": website.unwrap_or_default(),
+                "description": description(website),
+            }));
+        let alias: Alias = send(self, request)?;
+
+        Ok(format!(
+            "{}@{}",
+            alias.name,
+            alias
+                .domain
+                .map_or(domain.to_string(), |domain| domain.name)
+        ))
+    }
+}
+
+fn client() -> Client {
+    Client::builder()
+        .user_agent(format!(
+            "{}/{}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        ))
+        .build()
+        .unwrap()
+}
+
+fn trim(base_url: &str) -> &str {
+    base_url.trim_end_matches('/')
+}
+
+/// What to note on the alias, so it can be recognized in the service's own interface.
+fn description(website: Option<&str>) -> String {
+    match website {
+        Some(website) => format!("Website: {website}. Generated by bwrs."),
+        None => "Generated by bwrs.".to_string(),
+    }
+}
+
+/// Send a request to a provider, turning a failure into the provider's explanation of it.
+fn send<T: DeserializeOwned>(
+    provider: &(impl ForwardedEmailProvider + ?Sized),
+    request: RequestBuilder,
+) -> Result<T> {
+    let name = provider.name();
+    let res = request.send()?;
+
+    match res.status() {
+        status if status.is_success() => Ok(res.json()?),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(eyre!("Invalid {name} API token.")),
+        status => {
+            // Providers differ in where they put the reason.
+            let error = res.json::<Value>().unwrap_or_default();
+            let message = ["error", "message", "detail"]
+                .iter()
+                .find_map(|field| error[field].as_str());
+
+            Err(match message {
+                Some(message) => eyre!("{name} failed to make an alias: {message}"),
+                None => eyre!("{name} failed to make an alias: {status}"),
+            })
+        }
+    }
+}
@@ -0,0 +1,562 @@
+//! Random passwords, passphrases and usernames, made the way the official clients make them.
+
+use eyre::{eyre, Result};
+use rand::{rngs::OsRng, seq::SliceRandom, Rng};
+use serde::Deserialize;
+
+use crate::{
+    api::{Policy, PolicyKind},
+    crypto,
+};
+
+const LOWERCASE: &str = "abcdefghijkmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHJKLMNPQRSTUVWXYZ";
+const NUMBERS: &str = "23456789";
+const SPECIAL: &str = "!@#$%^&*";
+/// Characters which are easily mistaken for each other, left out of the sets above unless
+/// allowed.
+const AMBIGUOUS_LOWERCASE: &str = "l";
+const AMBIGUOUS_UPPERCASE: &str = "IO";
+const AMBIGUOUS_NUMBERS: &str = "01";
+/// The characters of the random part of generated email addresses.
+const EMAIL_CHARS: &str = "abcdefghijklmnopqrstuvwxyz1234567890";
+const EMAIL_RANDOM_LENGTH: usize = 8;
+
+pub const MIN_LENGTH: usize = 5;
+pub const MAX_LENGTH: usize = 128;
+pub const MIN_WORDS: usize = 3;
+pub const MAX_WORDS: usize = 20;
+
+/// How to generate a password. At least one kind of character must be allowed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PasswordOptions {
+    /// Clamped to between `MIN_LENGTH` and `MAX_LENGTH`, and raised to fit the required
+    /// characters.
+    pub length: usize,
+    pub uppercase: bool,
+    pub lowercase: bool,
+    pub numbers: bool,
+    pub special: bool,
+    /// The least number of digits, if digits are allowed. At least one is always included.
+    pub min_numbers: usize,
+    /// The least number of special characters, if they're allowed. At least one is always
+    /// included.
+    pub min_special: usize,
+    /// Leave out characters which are easily mistaken for each other, such as `l`, `I` and `1`.
+    pub avoid_ambiguous: bool,
+}
+impl Default for PasswordOptions {
+    fn default() -> Self {
+        Self {
+            length: 14,
+            uppercase: true,
+            lowercase: true,
+            numbers: true,
+            special: false,
+            min_numbers: 1,
+            min_special: 1,
+            avoid_ambiguous: false,
+        }
+    }
+}
+
+/// How to generate a passphrase of words from the EFF's long wordlist.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PassphraseOptions {
+    /// Clamped to between `MIN_WORDS` and `MAX_WORDS`.
+    pub words: usize,
+    pub separator: String,
+    /// Start each word with a capital letter.
+    pub capitalize: bool,
+    /// Append a digit to one of the words.
+    pub include_number: bool,
+}
+impl Default for PassphraseOptions {
+    fn default() -> Self {
+        Self {
+            words: 3,
+            separator: "-".to_string(),
+            capitalize: false,
+            include_number: false,
+        }
+    }
+}
+
+/// How to generate a username, like the web vault's username generator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UsernameOptions {
+    /// A random word from the EFF's long wordlist.
+    Word {
+        capitalize: bool,
+        /// Append four random digits.
+        include_number: bool,
+    },
+    /// A plus-addressed email, e.g. `user+x7kq2mfa@example.com`, for mail providers which
+    /// deliver these to `user@example.com`.
+    Subaddress {
+        email: String,
+        /// Use the website's name as the address tag, instead of random characters.
+        website: Option<String>,
+    },
+    /// An address at a domain which accepts mail for any address, e.g. `x7kq2mfa@example.com`.
+    Catchall {
+        domain: String,
+        /// Use the website's name as the address, instead of random characters.
+        website: Option<String>,
+    },
+}
+
+/// Ordered so that the stricter passphrase compares greater.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum GeneratorType {
+    Password,
+    Passphrase,
+}
+
+/// What the `PasswordGenerator` policies of the user's organizations require of generated
+/// passwords and passphrases.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct GeneratorPolicy {
+    pub required_type: Option<GeneratorType>,
+    pub min_length: usize,
+    pub uppercase: bool,
+    pub lowercase: bool,
+    pub numbers: bool,
+    pub special: bool,
+    pub min_numbers: usize,
+    pub min_special: usize,
+    pub min_words: usize,
+    pub capitalize: bool,
+    pub include_number: bool,
+}
+
+/// A `PasswordGenerator` policy's settings, any of which may be left out.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct PolicyData {
+    /// Called `defaultType` by older servers.
+    #[serde(alias = "defaultType")]
+    override_password_type: Option<String>,
+    min_length: Option<usize>,
+    use_upper: Option<bool>,
+    use_lower: Option<bool>,
+    use_numbers: Option<bool>,
+    use_special: Option<bool>,
+    min_numbers: Option<usize>,
+    min_special: Option<usize>,
+    min_number_words: Option<usize>,
+    capitalize: Option<bool>,
+    include_number: Option<bool>,
+}
+
+impl GeneratorPolicy {
+    /// Combine the enabled `PasswordGenerator` policies, taking the strictest of each setting.
+    /// A passphrase wins over a password if organizations disagree. `None` if there are none.
+    pub fn from_policies(policies: &[Policy]) -> Result<Option<Self>> {
+        let mut combined = None;
+        for policy in policies {
+            if policy.kind != PolicyKind::PasswordGenerator || !policy.enabled {
+                continue;
+            }
+            let data = match &policy.data {
+                Some(data) => serde_json::from_value::<PolicyData>(data.clone())?,
+                None => PolicyData::default(),
+            };
+
+            let combined: &mut Self = combined.get_or_insert_with(Self::default);
+            let required_type = match data.override_password_type.as_deref() {
+                Some("password") => Some(GeneratorType::Password),
+                Some("passphrase") => Some(GeneratorType::Passphrase),
+                _ => None,
+            };
+            combined.required_type = combined.required_type.max(required_type);
+            combined.min_length = combined.min_length.max(data.min_length.unwrap_or(0));
+            combined.uppercase |= data.use_upper.unwrap_or(false);
+            combined.lowercase |= data.use_lower.unwrap_or(false);
+            combined.numbers |= data.use_numbers.unwrap_or(false);
+            combined.special |= data.use_special.unwrap_or(false);
+            combined.min_numbers = combined.min_numbers.max(data.min_numbers.unwrap_or(0));
+            combined.min_special = combined.min_special.max(data.min_special.unwrap_or(0));
+            combined.min_words = combined.min_words.max(data.min_number_words.unwrap_or(0));
+            combined.capitalize |= data.capitalize.unwrap_or(false);
+            combined.include_number |= data.include_number.unwrap_or(false);
+        }
+
+        Ok(combined)
+    }
+
+    /// Tighten password options to satisfy the policy. Returns a description of each option
+    /// which had to change.
+    pub fn enforce_password(&self, options: &mut PasswordOptions) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if options.length < self.min_length {
+            options.length = self.min_length;
+            changes.push(format!("The length was raised to {}.", self.min_length));
+        }
+        for (required, allowed, name) in [
+            (
+                self.uppercase,
+                &mut options.uppercase,
+                "Uppercase characters",
+            ),
+            (
+                self.lowercase,
+                &mut options.lowercase,
+                "Lowercase characters",
+            ),
+            (self.numbers, &mut options.numbers, "Numbers"),
+            (self.special, &mut options.special, "Special characters"),
+        ] {
+            if required && !*allowed {
+                *allowed = true;
+                changes.push(format!("{name} are required."));
+            }
+        }
+        if options.min_numbers < self.min_numbers {
+            options.min_numbers = self.min_numbers;
+            changes.push(format!(
+                "The minimum number of numbers was raised to {}.",
+                self.min_numbers
+            ));
+        }
+        if options.min_special < self.min_special {
+            options.min_special = self.min_special;
+            changes.push(format!(
+                "The minimum number of special characters was raised to {}.",
+                self.min_special
+            ));
+        }
+
+        changes
+    }
+
+    /// Tighten passphrase options to satisfy the policy. Returns a description of each option
+    /// which had to change.
+    pub fn enforce_passphrase(&self, options: &mut PassphraseOptions) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if options.words < self.min_words {
+            options.words = self.min_words;
+            changes.push(format!(
+                "The number of words was raised to {}.",
+                self.min_words
+            ));
+        }
+        if self.capitalize && !options.capitalize {
+            options.capitalize = true;
+            changes.push("Capitalized words are required.".to_string());
+        }
+        if self.include_number && !options.include_number {
+            options.include_number = true;
+            changes.push("A number is required.".to_string());
+        }
+
+        changes
+    }
+}
+
+/// Generate a password. Each allowed kind of character appears at least once, or as often as
+/// required, at random positions, and the rest are drawn from every allowed character.
+pub fn password(options: &PasswordOptions) -> Result<String> {
+    let ambiguous = |extra: &'static str| if options.avoid_ambiguous { "" } else { extra };
+    let classes = [
+        (
+            options.lowercase,
+            LOWERCASE,
+            ambiguous(AMBIGUOUS_LOWERCASE),
+            1,
+        ),
+        (
+            options.uppercase,
+            UPPERCASE,
+            ambiguous(AMBIGUOUS_UPPERCASE),
+            1,
+        ),
+        (
+            options.numbers,
+            NUMBERS,
+            ambiguous(AMBIGUOUS_NUMBERS),
+            options.min_numbers.max(1),
+        ),
+        (options.special, SPECIAL, "", options.min_special.max(1)),
+    ]
+    .into_iter()
+    .filter(|(allowed, ..)| *allowed)
+    .map(|(_, chars, extra, min)| (chars.chars().chain(extra.chars()).collect::<Vec<_>>(), min))
+    .collect::<Vec<_>>();
+    if classes.is_empty() {
+        return Err(eyre!(
+            "At least one of uppercase, lowercase, numbers or special characters must be allowed."
+        ));
+    }
+
+    let all = classes
+        .iter()
+        .flat_map(|(chars, _)| chars.iter().copied())
+        .collect::<Vec<_>>();
+    let required = classes.iter().map(|(_, min)| min).sum::<usize>();
+    let length = options.length.clamp(MIN_LENGTH, MAX_LENGTH).max(required);
+
+    // Decide which kind of character goes where, then fill in each position.
+    let mut positions = classes
+        .iter()
+        .flat_map(|(chars, min)| std::iter::repeat(chars).take(*min))
+        .collect::<Vec<_>>();
+    positions.resize(length, &all);
+    positions.shuffle(&mut OsRng);
+
+    Ok(positions
+        .into_iter()
+        .map(|chars| *chars.choose(&mut OsRng).unwrap())
+        .collect())
+}
+
+/// Generate a passphrase of random words.
+pub fn passphrase(options: &PassphraseOptions) -> String {
+    let wordlist = crypto::wordlist();
+    let mut words = (0..options.words.clamp(MIN_WORDS, MAX_WORDS))
+        .map(|_| {
+            let word = *wordlist.choose(&mut OsRng).unwrap();
+            if options.capitalize {
+                capitalize(word)
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if options.include_number {
+        let word = OsRng.gen_range(0..words.len());
+        words[word].push_str(&OsRng.gen_range(0..10).to_string());
+    }
+
+    words.join(&options.separator)
+}
+
+/// Generate a username.
+pub fn username(options: &UsernameOptions) -> Result<String> {
+    match options {
+        UsernameOptions::Word {
+            capitalize: upper,
+            include_number,
+        } => {
+            let word = *crypto::wordlist().choose(&mut OsRng).unwrap();
+            let mut username = if *upper {
+                capitalize(word)
+            } else {
+                word.to_string()
+            };
+            if *include_number {
+                username.push_str(&format!("{:04}", OsRng.gen_range(1..=9999)));
+            }
+
+            Ok(username)
+        }
+        UsernameOptions::Subaddress { email, website } => {
+            let (user, domain) = email
+                .trim()
+                .split_once('@')
+                .filter(|(user, domain)| !user.is_empty() && !domain.is_empty())
+                .ok_or_else(|| eyre!("`{email}` is not an email address."))?;
+
+            Ok(format!("{user}+{}@{domain}", email_tag(website.as_deref())))
+        }
+        UsernameOptions::Catchall { domain, website } => {
+            let domain = domain.trim().trim_start_matches('@');
+            if domain.is_empty() {
+                return Err(eyre!("A catch-all email needs a domain."));
+            }
+
+            Ok(format!("{}@{domain}", email_tag(website.as_deref())))
+        }
+    }
+}
+
+/// The website's name, or else random characters, to tell generated email addresses apart.
+fn email_tag(website: Option<&str>) -> String {
+    match website {
+        Some(website) => website.trim().to_string(),
+        None => {
+            let chars = EMAIL_CHARS.chars().collect::<Vec<_>>();
+            (0..EMAIL_RANDOM_LENGTH)
+                .map(|_| *chars.choose(&mut OsRng).unwrap())
+                .collect()
+        }
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Whether counts which should be uniform pass Pearson's chi-square test. The critical value
+    /// is the Wilson-Hilferty approximation at p = 10^-6, so a fair generator fails about once in
+    /// a million runs.
+    fn is_uniform(counts: &[usize]) -> bool {
+        let total = counts.iter().sum::<usize>() as f64;
+        let expected = total / counts.len() as f64;
+        let statistic = counts
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum::<f64>();
+
+        let df = (counts.len() - 1) as f64;
+        let z = 4.753;
+        let critical = df * (1.0 - 2.0 / (9.0 * df) + z * (2.0 / (9.0 * df)).sqrt()).powi(3);
+
+        statistic < critical
+    }
+
+    fn count_in(password: &str, chars: &str) -> usize {
+        password.chars().filter(|c| chars.contains(*c)).count()
+    }
+
+    #[test]
+    fn every_allowed_class_appears_as_often_as_required() {
+        for classes in 1..16 {
+            let options = PasswordOptions {
+                length: MIN_LENGTH,
+                lowercase: classes & 1 != 0,
+                uppercase: classes & 2 != 0,
+                numbers: classes & 4 != 0,
+                special: classes & 8 != 0,
+                min_numbers: 3,
+                min_special: 2,
+                avoid_ambiguous: false,
+            };
+            let lowercase = format!("{LOWERCASE}{AMBIGUOUS_LOWERCASE}");
+            let uppercase = format!("{UPPERCASE}{AMBIGUOUS_UPPERCASE}");
+            let numbers = format!("{NUMBERS}{AMBIGUOUS_NUMBERS}");
+            let checks = [
+                (options.lowercase, lowercase.as_str(), 1),
+                (options.uppercase, uppercase.as_str(), 1),
+                (options.numbers, numbers.as_str(), 3),
+                (options.special, SPECIAL, 2),
+            ];
+            let required = checks
+                .iter()
+                .filter(|(allowed, ..)| *allowed)
+                .map(|(.., min)| min)
+                .sum::<usize>();
+
+            for _ in 0..500 {
+                let password = password(&options).unwrap();
+                assert_eq!(password.chars().count(), MIN_LENGTH.max(required));
+                for (allowed, chars, min) in checks {
+                    let count = count_in(&password, chars);
+                    if allowed {
+                        assert!(count >= min, "{password} has {count} of {chars}");
+                    } else {
+                        assert_eq!(count, 0, "{password} has {chars}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_classes_is_an_error() {
+        let options = PasswordOptions {
+            lowercase: false,
+            uppercase: false,
+            numbers: false,
+            special: false,
+            ..PasswordOptions::default()
+        };
+
+        assert!(password(&options).is_err());
+    }
+
+    #[test]
+    fn ambiguous_characters_can_be_left_out() {
+        let ambiguous = format!("{AMBIGUOUS_LOWERCASE}{AMBIGUOUS_UPPERCASE}{AMBIGUOUS_NUMBERS}");
+        let mut options = PasswordOptions {
+            length: MAX_LENGTH,
+            special: true,
+            avoid_ambiguous: true,
+            ..PasswordOptions::default()
+        };
+
+        for _ in 0..500 {
+            let password = password(&options).unwrap();
+            assert_eq!(count_in(&password, &ambiguous), 0, "{password}");
+        }
+
+        options.avoid_ambiguous = false;
+        let seen = (0..500)
+            .map(|_| password(&options).unwrap())
+            .collect::<String>();
+        assert!(ambiguous.chars().all(|c| seen.contains(c)));
+    }
+
+    #[test]
+    fn characters_are_uniform_within_each_class() {
+        // Required positions favor their own class, so only each class on its own is uniform.
+        let options = PasswordOptions {
+            length: MAX_LENGTH,
+            special: true,
+            min_numbers: 5,
+            min_special: 5,
+            ..PasswordOptions::default()
+        };
+        let mut counts = HashMap::<char, usize>::new();
+        for _ in 0..2_000 {
+            for c in password(&options).unwrap().chars() {
+                *counts.entry(c).or_default() += 1;
+            }
+        }
+
+        for class in [
+            format!("{LOWERCASE}{AMBIGUOUS_LOWERCASE}"),
+            format!("{UPPERCASE}{AMBIGUOUS_UPPERCASE}"),
+            format!("{NUMBERS}{AMBIGUOUS_NUMBERS}"),
+            SPECIAL.to_string(),
+        ] {
+            let class_counts = class
+                .chars()
+                .map(|c| counts.get(&c).copied().unwrap_or(0))
+                .collect::<Vec<_>>();
+            assert!(is_uniform(&class_counts), "{class}: {class_counts:?}");
+        }
+    }
+
+    #[test]
+    fn passphrase_words_are_uniform() {
+        let wordlist = crypto::wordlist();
+        let index = wordlist
+            .iter()
+            .enumerate()
+            .map(|(i, word)| (*word, i))
+            .collect::<HashMap<_, _>>();
+        // Some words have hyphens in them.
+        let options = PassphraseOptions {
+            words: MAX_WORDS,
+            separator: " ".to_string(),
+            ..PassphraseOptions::default()
+        };
+
+        let mut counts = vec![0; wordlist.len()];
+        for _ in 0..wordlist.len() {
+            let passphrase = passphrase(&options);
+            let words = passphrase.split(' ').collect::<Vec<_>>();
+            assert_eq!(words.len(), MAX_WORDS);
+            for word in words {
+                counts[index[word]] += 1;
+            }
+        }
+
+        assert!(is_uniform(&counts));
+    }
+}
@@ -1,2 +1,11 @@
+#[cfg(unix)]
+pub mod agent;
 pub mod api;
+pub mod audit;
 pub mod crypto;
+pub mod forwarder;
+pub mod generator;
+pub mod notifications;
+pub mod storage;
+pub mod totp;
+pub mod vault;
@@ -0,0 +1,566 @@
+use std::{
+    io::ErrorKind,
+    net::TcpStream,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use eyre::{eyre, Result, WrapErr};
+use serde_json::Value;
+use tungstenite::{stream::MaybeTlsStream, Message as WsMessage, WebSocket};
+use uuid::Uuid;
+
+use crate::api::Unauthorized;
+
+/// Separates SignalR JSON messages, and terminates the handshake in both protocols.
+const RECORD_SEPARATOR: char = '\u{1e}';
+/// SignalR servers drop clients which haven't sent anything for 30 seconds.
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+/// How long [`Hub::receive`] waits for a message before returning control to the caller.
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+const INVOCATION: u64 = 1;
+const PING: u64 = 6;
+const CLOSE: u64 = 7;
+
+/// The SignalR hub protocol used to encode messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Json,
+    MessagePack,
+}
+impl Protocol {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::MessagePack => "messagepack",
+        }
+    }
+}
+impl FromStr for Protocol {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Self::Json),
+            "messagepack" => Ok(Self::MessagePack),
+            s => Err(eyre!("Unknown hub protocol: {s}")),
+        }
+    }
+}
+
+/// A change pushed by the server.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Notification {
+    /// A cipher was created or changed, and should be fetched again.
+    SyncCipherUpdate {
+        id: Uuid,
+    },
+    SyncCipherDelete {
+        id: Uuid,
+    },
+    /// A folder was created or changed, and should be fetched again.
+    SyncFolderUpdate {
+        id: Uuid,
+    },
+    SyncFolderDelete {
+        id: Uuid,
+    },
+    /// Too much changed to apply incrementally, so the whole vault should be synced.
+    SyncVault,
+    /// The session was revoked, e.g. because the master password changed.
+    LogOut,
+    /// Another device is asking this one to approve a login.
+    AuthRequest {
+        id: Uuid,
+    },
+    /// A notification we don't act on.
+    Other(u64),
+}
+
+/// A notification, along with the device which caused it.
+#[derive(Debug)]
+pub struct Message {
+    /// The identifier of the device which made the change, so our own changes can be ignored.
+    pub context_id: Option<String>,
+    pub notification: Notification,
+}
+
+/// A connection to the server's `/hub` endpoint.
+pub struct Hub {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    protocol: Protocol,
+    last_ping: Instant,
+}
+impl Hub {
+    pub fn connect(
+        notifications_url: &str,
+        access_token: &str,
+        protocol: Protocol,
+    ) -> Result<Self> {
+        let url = format!("{notifications_url}/hub?access_token={access_token}");
+        let url = match url.split_once("://") {
+            Some(("https", rest)) => format!("wss://{rest}"),
+            Some(("http", rest)) => format!("ws://{rest}"),
+            _ => url,
+        };
+
+        let (mut socket, _) = match tungstenite::connect(url.as_str()) {
+            Ok(connection) => connection,
+            Err(tungstenite::Error::Http(res)) if res.status().as_u16() == 401 => {
+                return Err(Unauthorized.into())
+            }
+            Err(e) => {
+                return Err(e)
+                    .wrap_err_with(|| format!("Failed to connect to {notifications_url}/hub"))
+            }
+        };
+        let stream = match socket.get_mut() {
+            MaybeTlsStream::Plain(stream) => stream,
+            MaybeTlsStream::NativeTls(stream) => stream.get_mut(),
+            _ => unreachable!("only native-tls is enabled"),
+        };
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        socket.send(WsMessage::text(format!(
+            "{{\"protocol\":\"{}\",\"version\":1}}{RECORD_SEPARATOR}",
+            protocol.name()
+        )))?;
+
+        Ok(Self {
+            socket,
+            protocol,
+            last_ping: Instant::now(),
+        })
+    }
+
+    /// Wait briefly for the next batch of notifications, keeping the connection alive. Returns
+    /// an empty batch if nothing arrived, so the caller can check whether to keep listening.
+    pub fn receive(&mut self) -> Result<Vec<Message>> {
+        if self.last_ping.elapsed() >= PING_INTERVAL {
+            self.ping()?;
+        }
+
+        let message = match self.socket.read() {
+            Ok(message) => message,
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                return Ok(Vec::new())
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let records = match message {
+            // The handshake response is always JSON, even when using MessagePack.
+            WsMessage::Text(text) => text
+                .split(RECORD_SEPARATOR)
+                .filter(|record| !record.is_empty())
+                .map(|record| Ok(serde_json::from_str(record)?))
+                .collect::<Result<Vec<Value>>>()?,
+            WsMessage::Binary(bytes) => split_messagepack(&bytes)?,
+            WsMessage::Close(_) => return Err(eyre!("The hub closed the connection")),
+            _ => return Ok(Vec::new()),
+        };
+
+        let mut messages = Vec::new();
+        for record in records {
+            if let Some(error) = record.get("error").and_then(Value::as_str) {
+                return Err(eyre!("The hub rejected the connection: {error}"));
+            }
+
+            match record.get("type").and_then(Value::as_u64) {
+                Some(INVOCATION) if record["target"] == "ReceiveMessage" => {
+                    if let Some(argument) = record["arguments"].get(0) {
+                        messages.push(parse_notification(argument)?);
+                    }
+                }
+                Some(CLOSE) => return Err(eyre!("The hub closed the connection")),
+                _ => {}
+            }
+        }
+
+        Ok(messages)
+    }
+
+    fn ping(&mut self) -> Result<()> {
+        self.socket.send(match self.protocol {
+            Protocol::Json => WsMessage::text(format!("{{\"type\":{PING}}}{RECORD_SEPARATOR}")),
+            // A length prefix of 1, then a one element array holding the message type.
+            Protocol::MessagePack => WsMessage::binary([0x01, 0x91, PING as u8]),
+        })?;
+        self.last_ping = Instant::now();
+
+        Ok(())
+    }
+}
+
+/// Split a binary frame into its length-prefixed messages, converting each from the positional
+/// MessagePack layout to the same shape as a JSON message.
+fn split_messagepack(mut bytes: &[u8]) -> Result<Vec<Value>> {
+    let mut records = Vec::new();
+
+    while !bytes.is_empty() {
+        let mut len = 0usize;
+        for shift in (0..35).step_by(7) {
+            let (&byte, rest) = bytes
+                .split_first()
+                .ok_or_else(|| eyre!("Truncated frame"))?;
+            bytes = rest;
+            len |= usize::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        if len > bytes.len() {
+            return Err(eyre!("Truncated frame"));
+        }
+
+        let (mut record, rest) = bytes.split_at(len);
+        bytes = rest;
+
+        let fields = match rmpv::decode::read_value(&mut record)? {
+            rmpv::Value::Array(fields) => fields,
+            _ => continue,
+        };
+        // Invocations are [type, headers, invocationId, target, arguments].
+        let record = match fields.first().and_then(rmpv::Value::as_u64) {
+            Some(INVOCATION) => serde_json::json!({
+                "type": INVOCATION,
+                "target": fields.get(3).and_then(rmpv::Value::as_str),
+                "arguments": fields.get(4).map_or(Value::Null, messagepack_to_json),
+            }),
+            Some(kind) => serde_json::json!({ "type": kind }),
+            None => continue,
+        };
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+fn messagepack_to_json(value: &rmpv::Value) -> Value {
+    match value {
+        rmpv::Value::Boolean(b) => Value::Bool(*b),
+        rmpv::Value::Integer(i) => i
+            .as_u64()
+            .map(Value::from)
+            .or_else(|| i.as_i64().map(Value::from))
+            .unwrap_or(Value::Null),
+        rmpv::Value::F32(f) => Value::from(f64::from(*f)),
+        rmpv::Value::F64(f) => Value::from(*f),
+        rmpv::Value::String(s) => s.as_str().map_or(Value::Null, Value::from),
+        rmpv::Value::Array(values) => values.iter().map(messagepack_to_json).collect(),
+        rmpv::Value::Map(entries) => entries
+            .iter()
+            .filter_map(|(k, v)| Some((k.as_str()?.to_string(), messagepack_to_json(v))))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        // Binary blobs and extensions (e.g. timestamps) aren't needed by any notification.
+        rmpv::Value::Nil | rmpv::Value::Binary(_) | rmpv::Value::Ext(..) => Value::Null,
+    }
+}
+
+/// Look up a field which may be PascalCase (MessagePack) or camelCase (JSON).
+fn field<'a>(value: &'a Value, pascal: &str) -> &'a Value {
+    let mut camel = pascal.to_string();
+    camel[..1].make_ascii_lowercase();
+
+    value
+        .get(pascal)
+        .or_else(|| value.get(&camel))
+        .unwrap_or(&Value::Null)
+}
+
+fn parse_notification(argument: &Value) -> Result<Message> {
+    let payload = field(argument, "Payload");
+    let id = || -> Result<Uuid> {
+        Ok(field(payload, "Id")
+            .as_str()
+            .ok_or_else(|| eyre!("Notification is missing an id"))?
+            .parse()?)
+    };
+
+    let kind = field(argument, "Type")
+        .as_u64()
+        .ok_or_else(|| eyre!("Notification is missing a type"))?;
+    let notification = match kind {
+        // SyncCipherUpdate, SyncCipherCreate
+        0 | 1 => Notification::SyncCipherUpdate { id: id()? },
+        // SyncLoginDelete, SyncCipherDelete
+        2 | 9 => Notification::SyncCipherDelete { id: id()? },
+        3 => Notification::SyncFolderDelete { id: id()? },
+        // SyncCiphers, SyncVault, SyncOrgKeys, SyncSettings
+        4 | 5 | 6 | 10 => Notification::SyncVault,
+        // SyncFolderCreate, SyncFolderUpdate
+        7 | 8 => Notification::SyncFolderUpdate { id: id()? },
+        11 => Notification::LogOut,
+        15 => Notification::AuthRequest { id: id()? },
+        kind => Notification::Other(kind),
+    };
+
+    Ok(Message {
+        context_id: field(argument, "ContextId").as_str().map(str::to_string),
+        notification,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread::{self, JoinHandle},
+    };
+
+    use tungstenite::handshake::server::{Request, Response};
+
+    use super::*;
+
+    const CIPHER_ID: &str = "10000000-0000-0000-0000-000000000001";
+    const FOLDER_ID: &str = "20000000-0000-0000-0000-000000000002";
+    const AUTH_REQUEST_ID: &str = "30000000-0000-0000-0000-000000000003";
+
+    /// Accept one client, check its handshake, answer it and send `frames`, then wait for the
+    /// client to hang up.
+    fn fake_hub(protocol: Protocol, frames: Vec<WsMessage>) -> (String, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept_hdr(stream, |req: &Request, res: Response| {
+                assert_eq!(req.uri().path(), "/hub");
+                assert_eq!(req.uri().query(), Some("access_token=token"));
+                Ok(res)
+            })
+            .unwrap();
+
+            let handshake = socket.read().unwrap();
+            assert_eq!(
+                handshake.into_text().unwrap(),
+                format!(
+                    "{{\"protocol\":\"{}\",\"version\":1}}{RECORD_SEPARATOR}",
+                    protocol.name()
+                )
+            );
+            socket
+                .send(WsMessage::text(format!("{{}}{RECORD_SEPARATOR}")))
+                .unwrap();
+            for frame in frames {
+                socket.send(frame).unwrap();
+            }
+
+            while socket.read().is_ok() {}
+        });
+
+        (url, server)
+    }
+
+    /// Receive until `count` notifications have arrived.
+    fn receive(hub: &mut Hub, count: usize) -> Vec<Message> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut messages = Vec::new();
+        while messages.len() < count {
+            assert!(
+                Instant::now() < deadline,
+                "timed out waiting for notifications"
+            );
+            messages.extend(hub.receive().unwrap());
+        }
+
+        messages
+    }
+
+    fn json_invocation(kind: u64, id: Option<&str>, context_id: &str) -> String {
+        serde_json::json!({
+            "type": INVOCATION,
+            "target": "ReceiveMessage",
+            "arguments": [{
+                "contextId": context_id,
+                "type": kind,
+                "payload": id.map(|id| serde_json::json!({ "id": id })),
+            }],
+        })
+        .to_string()
+    }
+
+    fn messagepack_invocation(kind: u64, id: Option<&str>, context_id: &str) -> Vec<u8> {
+        let payload = match id {
+            Some(id) => rmpv::Value::Map(vec![("Id".into(), id.into())]),
+            None => rmpv::Value::Nil,
+        };
+        let argument = rmpv::Value::Map(vec![
+            ("ContextId".into(), context_id.into()),
+            ("Type".into(), kind.into()),
+            ("Payload".into(), payload),
+        ]);
+        let record = rmpv::Value::Array(vec![
+            INVOCATION.into(),
+            rmpv::Value::Map(Vec::new()),
+            rmpv::Value::Nil,
+            "ReceiveMessage".into(),
+            rmpv::Value::Array(vec![argument]),
+        ]);
+
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, &record).unwrap();
+        bytes
+    }
+
+    /// Prefix a MessagePack record with its length as a varint.
+    fn length_prefixed(record: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        let mut len = record.len();
+        loop {
+            let byte = (len & 0x7f) as u8;
+            len >>= 7;
+            if len == 0 {
+                frame.push(byte);
+                break;
+            }
+            frame.push(byte | 0x80);
+        }
+        frame.extend(record);
+
+        frame
+    }
+
+    #[test]
+    fn json_records_are_split_on_the_record_separator() {
+        // Several records in one frame, including a ping which should be skipped.
+        let frame = [
+            json_invocation(0, Some(CIPHER_ID), "other-device"),
+            format!("{{\"type\":{PING}}}"),
+            json_invocation(3, Some(FOLDER_ID), "other-device"),
+        ]
+        .map(|record| record + &RECORD_SEPARATOR.to_string())
+        .concat();
+        let frames = vec![
+            WsMessage::text(frame),
+            WsMessage::text(json_invocation(11, None, "this-device") + "\u{1e}"),
+        ];
+        let (url, server) = fake_hub(Protocol::Json, frames);
+
+        let mut hub = Hub::connect(&url, "token", Protocol::Json).unwrap();
+        let messages = receive(&mut hub, 3);
+        drop(hub);
+        server.join().unwrap();
+
+        let notifications = messages
+            .iter()
+            .map(|message| &message.notification)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            notifications,
+            [
+                &Notification::SyncCipherUpdate {
+                    id: CIPHER_ID.parse().unwrap()
+                },
+                &Notification::SyncFolderDelete {
+                    id: FOLDER_ID.parse().unwrap()
+                },
+                &Notification::LogOut,
+            ]
+        );
+        assert_eq!(messages[0].context_id.as_deref(), Some("other-device"));
+        assert_eq!(messages[2].context_id.as_deref(), Some("this-device"));
+    }
+
+    #[test]
+    fn messagepack_records_are_split_on_varint_lengths() {
+        // A context id long enough to need a two byte length prefix.
+        let long_context = "d".repeat(200);
+        let auth_request = messagepack_invocation(15, Some(AUTH_REQUEST_ID), &long_context);
+        assert!(auth_request.len() > 0x7f);
+
+        let mut frame = length_prefixed(&messagepack_invocation(5, None, "other-device"));
+        frame.extend(length_prefixed(&[0x91, PING as u8]));
+        frame.extend(length_prefixed(&auth_request));
+        let frames = vec![
+            WsMessage::binary(frame),
+            WsMessage::binary(length_prefixed(&messagepack_invocation(
+                1,
+                Some(CIPHER_ID),
+                "other-device",
+            ))),
+        ];
+        let (url, server) = fake_hub(Protocol::MessagePack, frames);
+
+        let mut hub = Hub::connect(&url, "token", Protocol::MessagePack).unwrap();
+        let messages = receive(&mut hub, 3);
+        drop(hub);
+        server.join().unwrap();
+
+        let notifications = messages
+            .iter()
+            .map(|message| &message.notification)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            notifications,
+            [
+                &Notification::SyncVault,
+                &Notification::AuthRequest {
+                    id: AUTH_REQUEST_ID.parse().unwrap()
+                },
+                &Notification::SyncCipherUpdate {
+                    id: CIPHER_ID.parse().unwrap()
+                },
+            ]
+        );
+        assert_eq!(
+            messages[1].context_id.as_deref(),
+            Some(long_context.as_str())
+        );
+    }
+
+    #[test]
+    fn truncated_messagepack_frames_are_rejected() {
+        let mut frame = length_prefixed(&messagepack_invocation(5, None, "other-device"));
+        frame.pop();
+
+        assert!(split_messagepack(&frame).is_err());
+        assert!(split_messagepack(&[0x80]).is_err());
+    }
+
+    #[test]
+    fn handshake_errors_are_reported() {
+        let (url, server) = fake_hub(
+            Protocol::Json,
+            vec![WsMessage::text(format!(
+                "{{\"error\":\"Unsupported protocol\"}}{RECORD_SEPARATOR}"
+            ))],
+        );
+
+        let mut hub = Hub::connect(&url, "token", Protocol::Json).unwrap();
+        let error = loop {
+            match hub.receive() {
+                Ok(messages) => assert!(messages.is_empty()),
+                Err(e) => break e,
+            }
+        };
+        drop(hub);
+        server.join().unwrap();
+
+        assert!(error.to_string().contains("Unsupported protocol"));
+    }
+
+    #[test]
+    fn rejected_access_tokens_are_unauthorized() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let error = Hub::connect(&url, "token", Protocol::Json).err().unwrap();
+        server.join().unwrap();
+
+        assert!(error.downcast_ref::<Unauthorized>().is_some());
+    }
+}
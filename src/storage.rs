@@ -0,0 +1,664 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use eyre::{eyre, OptionExt, Result, WrapErr};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        access_token_user_id, ApiClient, KdfConfig, Policy, PolicyKind, ServerConfig, Unauthorized,
+        FEATURE_CIPHER_KEY_ENCRYPTION,
+    },
+    crypto::{EncString, PrivateKey, SymmetricKey},
+};
+
+/// The profile used when no account has been named or switched to.
+pub const DEFAULT_PROFILE: &str = "default";
+/// How long `bwrs` has the agent keep an unused account unlocked, unless configured otherwise:
+/// 15 minutes, like the official clients' default vault timeout.
+pub const DEFAULT_AGENT_IDLE_TIMEOUT: u64 = 15 * 60;
+/// How often `bwrsd` syncs unlocked accounts, unless configured otherwise, in seconds.
+pub const DEFAULT_AGENT_SYNC_INTERVAL: u64 = 5 * 60;
+
+const CONFIG_FILE: &str = "config.json";
+const PROFILES_DIR: &str = "accounts";
+
+const ACCOUNT_FILE: &str = "data.json";
+const SERVER_FILE: &str = "server.json";
+const VAULT_FILE: &str = "vault.json";
+const TWO_FACTOR_FILE: &str = "two_factor.json";
+const DEVICE_FILE: &str = "device_id";
+const LOCK_FILE: &str = "lock";
+
+/// Everything needed to talk to the server on behalf of a logged in user.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Account {
+    pub email: String,
+    /// Missing from accounts saved before it was recorded, so filled in from the access token
+    /// when read.
+    #[serde(default)]
+    pub user_id: Uuid,
+    pub kdf: KdfConfig,
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// The user key, encrypted with the stretched master key.
+    pub key: Option<String>,
+    /// The user's private key, encrypted with the user key.
+    pub private_key: Option<String>,
+    /// The user key, encrypted with the session key handed out when the vault was unlocked.
+    #[serde(default)]
+    pub protected_key: Option<String>,
+    /// When the vault cache was last refreshed, as an RFC 3339 timestamp.
+    #[serde(default)]
+    pub last_sync: Option<String>,
+}
+impl Account {
+    /// Unwrap the user key with a session key, as exported in `BW_SESSION`.
+    pub fn user_key(&self, session: &SymmetricKey) -> Result<SymmetricKey> {
+        let protected = self
+            .protected_key
+            .as_deref()
+            .ok_or_eyre("The vault is locked.")?
+            .parse::<EncString>()?;
+
+        SymmetricKey::from_slice(&session.decrypt(&protected)?)
+    }
+    /// Decrypt the account's private key, or `None` if it doesn't have one.
+    pub fn private_key(&self, user_key: &SymmetricKey) -> Result<Option<PrivateKey>> {
+        let Some(private_key) = &self.private_key else {
+            return Ok(None);
+        };
+        let der = zeroize::Zeroizing::new(user_key.decrypt(&private_key.parse()?)?);
+
+        Ok(Some(PrivateKey::from_der(&der)?))
+    }
+    /// Wrap the user key with a new session key, returning the session key.
+    pub fn protect_user_key(&mut self, user_key: &SymmetricKey) -> SymmetricKey {
+        let session = SymmetricKey::generate();
+        self.protected_key = Some(session.encrypt(user_key.as_bytes()).to_string());
+
+        session
+    }
+}
+
+/// Settings shared by every profile.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct GlobalConfig {
+    /// The profile used when none is given with `--account`.
+    pub active_account: Option<String>,
+    /// Whether to start `bwrsd` and hand it unlocked keys. Defaults to on.
+    #[serde(default)]
+    pub agent: Option<bool>,
+    /// Seconds an automatically started `bwrsd` keeps an account unlocked without its keys
+    /// being used. Defaults to [`DEFAULT_AGENT_IDLE_TIMEOUT`], and 0 turns it off.
+    #[serde(default)]
+    pub agent_idle_timeout: Option<u64>,
+    /// Seconds an automatically started `bwrsd` keeps an account unlocked, even if it's in use.
+    /// Off by default, or if 0.
+    #[serde(default)]
+    pub agent_absolute_timeout: Option<u64>,
+    /// How often an automatically started `bwrsd` syncs unlocked accounts, in seconds. Defaults
+    /// to [`DEFAULT_AGENT_SYNC_INTERVAL`], and 0 turns background sync off.
+    #[serde(default)]
+    pub agent_sync_interval: Option<u64>,
+}
+impl GlobalConfig {
+    /// The arguments which apply these settings to `bwrsd`.
+    pub fn agent_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut arg = |name: &str, value: u64| args.extend([name.to_string(), value.to_string()]);
+
+        match self
+            .agent_idle_timeout
+            .unwrap_or(DEFAULT_AGENT_IDLE_TIMEOUT)
+        {
+            0 => {}
+            idle => arg("--idle-timeout", idle),
+        }
+        match self.agent_absolute_timeout.unwrap_or(0) {
+            0 => {}
+            absolute => arg("--absolute-timeout", absolute),
+        }
+        arg(
+            "--sync-interval",
+            self.agent_sync_interval
+                .unwrap_or(DEFAULT_AGENT_SYNC_INTERVAL),
+        );
+
+        args
+    }
+}
+
+/// The on-disk state of `bwrs`, stored in `$BWRS_DATA_DIR` or the platform data directory.
+pub struct Storage {
+    dir: PathBuf,
+}
+impl Storage {
+    pub fn open() -> Result<Self> {
+        let dir = match std::env::var_os("BWRS_DATA_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => dirs::data_dir()
+                .ok_or_eyre("Unable to find a data directory")?
+                .join(env!("CARGO_PKG_NAME")),
+        };
+        create_private_dir(&dir)?;
+
+        Ok(Self { dir })
+    }
+
+    pub fn config(&self) -> Result<GlobalConfig> {
+        Ok(read(&self.dir.join(CONFIG_FILE))?.unwrap_or_default())
+    }
+    pub fn save_config(&self, config: &GlobalConfig) -> Result<()> {
+        write(&self.dir.join(CONFIG_FILE), config)
+    }
+
+    /// The names of every profile, sorted alphabetically.
+    pub fn profiles(&self) -> Result<Vec<String>> {
+        let dir = self.dir.join(PROFILES_DIR);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                names.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        names.sort();
+
+        Ok(names)
+    }
+
+    /// Open the named profile, falling back to the active profile and then [`DEFAULT_PROFILE`].
+    pub fn select(&self, name: Option<&str>) -> Result<Profile> {
+        match name {
+            Some(name) => self.profile(name),
+            None => self.profile(
+                self.config()?
+                    .active_account
+                    .as_deref()
+                    .unwrap_or(DEFAULT_PROFILE),
+            ),
+        }
+    }
+    /// Open a profile. Its directory is only created once something is saved to it.
+    pub fn profile(&self, name: &str) -> Result<Profile> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(eyre!(
+                "Invalid account name \"{name}\": use letters, numbers, '-', '_' and '.'"
+            ));
+        }
+
+        Ok(Profile {
+            name: name.to_string(),
+            dir: self.dir.join(PROFILES_DIR).join(name),
+        })
+    }
+    pub fn has_profile(&self, name: &str) -> bool {
+        self.dir.join(PROFILES_DIR).join(name).is_dir()
+    }
+
+    /// Make a profile the default for future commands.
+    pub fn switch(&self, name: &str) -> Result<()> {
+        if !self.has_profile(name) {
+            return Err(eyre!("No account named \"{name}\""));
+        }
+
+        let mut config = self.config()?;
+        config.active_account = Some(name.to_string());
+        self.save_config(&config)
+    }
+
+    /// Securely remove a profile and everything stored in it, returning the removed paths.
+    pub fn remove(&self, name: &str) -> Result<Vec<PathBuf>> {
+        if !self.has_profile(name) {
+            return Err(eyre!("No account named \"{name}\""));
+        }
+
+        let profile = self.profile(name)?;
+        let mut removed = profile.wipe()?;
+        for file in [SERVER_FILE, DEVICE_FILE] {
+            let path = profile.dir.join(file);
+            if secure_delete(&path)? {
+                removed.push(path);
+            }
+        }
+        fs::remove_dir_all(&profile.dir)?;
+
+        let mut config = self.config()?;
+        if config.active_account.as_deref() == Some(name) {
+            config.active_account = None;
+            self.save_config(&config)?;
+        }
+
+        Ok(removed)
+    }
+}
+
+/// A named account, with its own server, tokens, device id and vault cache.
+pub struct Profile {
+    name: String,
+    dir: PathBuf,
+}
+impl Profile {
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn account(&self) -> Result<Option<Account>> {
+        let mut account: Option<Account> = read(&self.dir.join(ACCOUNT_FILE))?;
+        if let Some(account) = account.as_mut().filter(|account| account.user_id.is_nil()) {
+            // An unreadable token will be replaced at the next refresh or login anyway.
+            if let Ok(user_id) = access_token_user_id(&account.access_token) {
+                account.user_id = user_id;
+            }
+        }
+
+        Ok(account)
+    }
+    pub fn save_account(&self, account: &Account) -> Result<()> {
+        write(&self.dir.join(ACCOUNT_FILE), account)
+    }
+
+    /// Take an exclusive lock on the profile, held until the guard is dropped, so `bwrs` and
+    /// `bwrsd` never interleave read-modify-write cycles on the same files.
+    pub fn lock(&self) -> Result<ProfileLock> {
+        create_private_dir(&self.dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(self.dir.join(LOCK_FILE))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsRawFd;
+
+            // SAFETY: the descriptor is valid for as long as `file` is borrowed.
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+
+        Ok(ProfileLock { _file: file })
+    }
+
+    /// Call the API with the account's access token, refreshing it once if it was rejected.
+    pub fn with_api<T>(&self, mut f: impl FnMut(&ApiClient, &str) -> Result<T>) -> Result<T> {
+        let client = ApiClient::from_config(&self.server()?);
+        let rejected = self
+            .account()?
+            .ok_or_eyre("You are not logged in.")?
+            .access_token;
+
+        match f(&client, &rejected) {
+            Err(e) if e.downcast_ref::<Unauthorized>().is_some() => {}
+            result => return result,
+        }
+
+        let access_token = {
+            let _lock = self.lock()?;
+            let mut account = self.account()?.ok_or_eyre("You are not logged in.")?;
+
+            // Another process may have already refreshed the token while we waited for the lock.
+            if account.access_token == rejected {
+                let session = client.refresh(
+                    account
+                        .refresh_token
+                        .as_deref()
+                        .ok_or_eyre("The session has expired. Log in again.")?,
+                )?;
+                account.access_token = session.access_token;
+                if session.refresh_token.is_some() {
+                    account.refresh_token = session.refresh_token;
+                }
+                self.save_account(&account)?;
+            }
+
+            account.access_token
+        };
+
+        f(&client, &access_token)
+    }
+
+    /// The encrypted vault, as returned by the last sync.
+    pub fn vault(&self) -> Result<Option<serde_json::Value>> {
+        read(&self.dir.join(VAULT_FILE))
+    }
+    /// Replace the vault cache with a fresh copy from the server, returning whether it changed.
+    pub fn sync(&self) -> Result<bool> {
+        let vault = self.with_api(|client, access_token| client.sync(access_token))?;
+
+        let _lock = self.lock()?;
+        // Don't resurrect the cache if the account logged out while we were syncing.
+        let mut account = self.account()?.ok_or_eyre("You are not logged in.")?;
+
+        let changed = self.vault()?.as_ref() != Some(&vault);
+        if changed {
+            write(&self.dir.join(VAULT_FILE), &vault)?;
+        }
+
+        account.last_sync = Some(humantime::format_rfc3339_millis(SystemTime::now()).to_string());
+        self.save_account(&account)?;
+
+        Ok(changed)
+    }
+    /// Apply an incremental change to the vault cache, returning whether `f` changed it. Nothing
+    /// happens if the vault hasn't been synced yet.
+    pub fn update_vault(
+        &self,
+        f: impl FnOnce(&mut serde_json::Value) -> Result<bool>,
+    ) -> Result<bool> {
+        let _lock = self.lock()?;
+        self.account()?.ok_or_eyre("You are not logged in.")?;
+
+        let Some(mut vault) = self.vault()? else {
+            return Ok(false);
+        };
+        let changed = f(&mut vault)?;
+        if changed {
+            write(&self.dir.join(VAULT_FILE), &vault)?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Replace the object with `id` in one of the vault cache's lists, adding it if it's new, or
+    /// remove it if `object` is `None`. Returns whether the cache changed.
+    pub fn upsert(&self, list: &str, id: Uuid, object: Option<serde_json::Value>) -> Result<bool> {
+        self.update_vault(|vault| {
+            let Some(objects) = vault
+                .get_mut(list)
+                .and_then(serde_json::Value::as_array_mut)
+            else {
+                return Ok(false);
+            };
+            let existing = objects.iter().position(|o| has_id(&o["id"], id));
+
+            match (existing, object) {
+                (Some(i), Some(object)) if objects[i] != object => objects[i] = object,
+                (None, Some(object)) => objects.push(object),
+                (Some(i), None) => {
+                    objects.remove(i);
+                }
+                _ => return Ok(false),
+            }
+
+            Ok(true)
+        })
+    }
+
+    /// Remove a deleted folder from the vault cache, along with any references to it from items,
+    /// which the server moves out of the folder. Returns whether the cache changed.
+    pub fn remove_folder(&self, id: Uuid) -> Result<bool> {
+        let mut changed = self.upsert("folders", id, None)?;
+        changed |= self.update_vault(|vault| {
+            let mut changed = false;
+            for cipher in objects(vault, "ciphers") {
+                if has_id(&cipher["folderId"], id) {
+                    cipher["folderId"] = serde_json::Value::Null;
+                    changed = true;
+                }
+            }
+
+            Ok(changed)
+        })?;
+
+        Ok(changed)
+    }
+    /// Remove a deleted collection from the vault cache, along with any references to it from
+    /// items. Returns whether the cache changed.
+    pub fn remove_collection(&self, id: Uuid) -> Result<bool> {
+        let mut changed = self.upsert("collections", id, None)?;
+        changed |= self.update_vault(|vault| {
+            let mut changed = false;
+            for cipher in objects(vault, "ciphers") {
+                if let Some(ids) = cipher["collectionIds"].as_array_mut() {
+                    let before = ids.len();
+                    ids.retain(|collection_id| !has_id(collection_id, id));
+                    changed |= ids.len() != before;
+                }
+            }
+
+            Ok(changed)
+        })?;
+
+        Ok(changed)
+    }
+
+    /// Unwrap the keys of every organization the user belongs to, by organization id, using the
+    /// memberships in the vault cache.
+    pub fn organization_keys(
+        &self,
+        account: &Account,
+        user_key: &SymmetricKey,
+    ) -> Result<BTreeMap<Uuid, SymmetricKey>> {
+        #[derive(Deserialize)]
+        struct Cache {
+            profile: CacheProfile,
+        }
+        #[derive(Deserialize)]
+        struct CacheProfile {
+            #[serde(default)]
+            organizations: Vec<Membership>,
+        }
+        #[derive(Deserialize)]
+        struct Membership {
+            id: Uuid,
+            key: Option<String>,
+        }
+
+        let Some(cache) = read::<Cache>(&self.dir.join(VAULT_FILE))? else {
+            return Ok(BTreeMap::new());
+        };
+        let Some(private_key) = account.private_key(user_key)? else {
+            return Ok(BTreeMap::new());
+        };
+
+        cache
+            .profile
+            .organizations
+            .into_iter()
+            .filter_map(|membership| Some((membership.id, membership.key?)))
+            .map(|(id, key)| {
+                let key = zeroize::Zeroizing::new(private_key.decrypt(&key.parse()?)?);
+
+                Ok((id, SymmetricKey::from_slice(&key)?))
+            })
+            .collect()
+    }
+
+    /// Whether new and edited items should get their own key, which the server decides with a
+    /// feature flag. The flag is off if the server can't be asked, since items encrypted with
+    /// the user or organization key are still readable everywhere.
+    pub fn cipher_key_encryption(&self) -> bool {
+        self.server()
+            .and_then(|server| ApiClient::from_config(&server).config())
+            .map_or(false, |features| {
+                features.enabled(FEATURE_CIPHER_KEY_ENCRYPTION)
+            })
+    }
+
+    pub fn server(&self) -> Result<ServerConfig> {
+        Ok(read(&self.dir.join(SERVER_FILE))?.unwrap_or_default())
+    }
+    pub fn save_server(&self, server: &ServerConfig) -> Result<()> {
+        write(&self.dir.join(SERVER_FILE), server)
+    }
+
+    /// The enabled policies of every organization the user belongs to, from the vault cache.
+    pub fn policies(&self) -> Result<Vec<Policy>> {
+        #[derive(Deserialize)]
+        struct Cache {
+            #[serde(default)]
+            policies: Vec<Policy>,
+        }
+
+        Ok(read::<Cache>(&self.dir.join(VAULT_FILE))?
+            .map(|cache| cache.policies)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|policy| policy.enabled)
+            .collect())
+    }
+    /// The strictest `MaximumVaultTimeout` across the user's organizations, in minutes.
+    pub fn maximum_vault_timeout(&self) -> Result<Option<u64>> {
+        Ok(self
+            .policies()?
+            .iter()
+            .filter(|policy| policy.kind == PolicyKind::MaximumVaultTimeout)
+            .filter_map(|policy| policy.data.as_ref()?.get("minutes")?.as_u64())
+            .min())
+    }
+
+    /// The identifier this profile presents to the server, created on first use.
+    pub fn device_id(&self) -> Result<Uuid> {
+        let path = self.dir.join(DEVICE_FILE);
+        match fs::read_to_string(&path) {
+            Ok(id) => Ok(id.trim().parse()?),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let id = Uuid::new_v4();
+                write_private(&path, id.to_string().as_bytes())?;
+
+                Ok(id)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The token handed out by the server when two-factor login was remembered for `email`.
+    pub fn two_factor_token(&self, email: &str) -> Result<Option<String>> {
+        let tokens: Option<serde_json::Map<String, serde_json::Value>> =
+            read(&self.dir.join(TWO_FACTOR_FILE))?;
+
+        Ok(tokens
+            .and_then(|mut tokens| tokens.remove(email))
+            .and_then(|token| token.as_str().map(str::to_string)))
+    }
+    pub fn save_two_factor_token(&self, email: &str, token: &str) -> Result<()> {
+        let path = self.dir.join(TWO_FACTOR_FILE);
+        let mut tokens: serde_json::Map<String, serde_json::Value> =
+            read(&path)?.unwrap_or_default();
+        tokens.insert(email.to_string(), token.into());
+
+        write(&path, &tokens)
+    }
+
+    /// Securely remove the account, vault cache and remembered two-factor tokens, returning
+    /// the paths which were removed.
+    pub fn wipe(&self) -> Result<Vec<PathBuf>> {
+        let _lock = self.lock()?;
+        let mut removed = Vec::new();
+        for file in [ACCOUNT_FILE, VAULT_FILE, TWO_FACTOR_FILE] {
+            let path = self.dir.join(file);
+            if secure_delete(&path)? {
+                removed.push(path);
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Guard returned by [`Profile::lock`].
+pub struct ProfileLock {
+    _file: File,
+}
+
+fn create_private_dir(dir: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    builder
+        .create(dir)
+        .wrap_err_with(|| format!("Failed to create {}", dir.display()))
+}
+
+fn read<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(
+            serde_json::from_slice(&bytes)
+                .wrap_err_with(|| format!("Failed to parse {}", path.display()))?,
+        )),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+fn write<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    write_private(path, &serde_json::to_vec(value)?)
+}
+
+/// Atomically replace `path` with `contents`, readable only by the current user.
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent)?;
+    }
+    // Unique per process, so concurrent writers never share a temporary file.
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options
+        .open(&tmp)
+        .wrap_err_with(|| format!("Failed to write {}", tmp.display()))?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+
+    Ok(())
+}
+
+/// Overwrite a file with zeroes before unlinking it. Returns `false` if it didn't exist.
+fn secure_delete(path: &Path) -> Result<bool> {
+    let len = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut file = File::options().write(true).open(path)?;
+    std::io::copy(&mut std::io::repeat(0).take(len), &mut file)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::remove_file(path).wrap_err_with(|| format!("Failed to remove {}", path.display()))?;
+
+    Ok(true)
+}
+
+/// The objects in one of the vault cache's lists.
+fn objects<'a>(
+    vault: &'a mut serde_json::Value,
+    list: &str,
+) -> impl Iterator<Item = &'a mut serde_json::Value> {
+    vault
+        .get_mut(list)
+        .and_then(serde_json::Value::as_array_mut)
+        .into_iter()
+        .flatten()
+}
+fn has_id(value: &serde_json::Value, id: Uuid) -> bool {
+    value.as_str().and_then(|s| s.parse::<Uuid>().ok()) == Some(id)
+}
@@ -0,0 +1,247 @@
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use eyre::{eyre, Result};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use url::Url;
+use zeroize::Zeroizing;
+
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+/// Steam Guard codes are five characters from this alphabet, rather than decimal digits.
+const STEAM_ALPHABET: &[u8] = b"23456789BCDFGHJKMNPQRTVWXY";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// A TOTP generator, parsed from a login's authenticator key. The key may be an `otpauth://`
+/// URI, a `steam://` URI, or a bare base32 secret.
+pub struct Totp {
+    secret: Zeroizing<Vec<u8>>,
+    algorithm: Algorithm,
+    digits: u32,
+    period: u64,
+    steam: bool,
+}
+impl FromStr for Totp {
+    type Err = eyre::Error;
+
+    fn from_str(key: &str) -> Result<Self> {
+        let key = key.trim();
+        let mut totp = Self {
+            secret: Zeroizing::new(Vec::new()),
+            algorithm: Algorithm::Sha1,
+            digits: 6,
+            period: 30,
+            steam: false,
+        };
+
+        let secret = if let Some(secret) = key.strip_prefix("steam://") {
+            totp.steam = true;
+            secret.to_string()
+        } else if key.to_ascii_lowercase().starts_with("otpauth://") {
+            let url = Url::parse(key)?;
+            let mut secret = None;
+
+            for (name, value) in url.query_pairs() {
+                match name.to_ascii_lowercase().as_str() {
+                    "secret" => secret = Some(value.into_owned()),
+                    "algorithm" => {
+                        totp.algorithm = match value.to_ascii_lowercase().as_str() {
+                            "sha1" => Algorithm::Sha1,
+                            "sha256" => Algorithm::Sha256,
+                            "sha512" => Algorithm::Sha512,
+                            _ => return Err(eyre!("Unsupported TOTP algorithm: {value}")),
+                        }
+                    }
+                    "digits" => match value.parse() {
+                        Ok(digits @ 1..=10) => totp.digits = digits,
+                        _ => return Err(eyre!("Invalid TOTP digits: {value}")),
+                    },
+                    "period" => match value.parse() {
+                        Ok(period @ 1..) => totp.period = period,
+                        _ => return Err(eyre!("Invalid TOTP period: {value}")),
+                    },
+                    "encoder" => totp.steam = value == "steam",
+                    _ => {}
+                }
+            }
+
+            secret.ok_or_else(|| eyre!("The otpauth URI has no secret"))?
+        } else {
+            key.to_string()
+        };
+
+        totp.secret = Zeroizing::new(decode_base32(&secret)?);
+        if totp.secret.is_empty() {
+            return Err(eyre!("The TOTP secret is empty"));
+        }
+
+        Ok(totp)
+    }
+}
+impl Totp {
+    /// The code for the current time.
+    pub fn now(&self) -> Result<String> {
+        Ok(self.at(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()))
+    }
+
+    /// The code for a Unix timestamp, in seconds.
+    pub fn at(&self, timestamp: u64) -> String {
+        let counter = (timestamp / self.period).to_be_bytes();
+        let hash = match self.algorithm {
+            Algorithm::Sha1 => hmac::<Hmac<Sha1>>(&self.secret, &counter),
+            Algorithm::Sha256 => hmac::<Hmac<Sha256>>(&self.secret, &counter),
+            Algorithm::Sha512 => hmac::<Hmac<Sha512>>(&self.secret, &counter),
+        };
+
+        // Dynamic truncation, as described in RFC 4226.
+        let offset = usize::from(hash[hash.len() - 1] & 0xf);
+        let mut code =
+            u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+
+        if self.steam {
+            (0..5)
+                .map(|_| {
+                    let c = STEAM_ALPHABET[code as usize % STEAM_ALPHABET.len()];
+                    code /= STEAM_ALPHABET.len() as u32;
+                    char::from(c)
+                })
+                .collect()
+        } else {
+            let code = u64::from(code) % 10u64.pow(self.digits);
+            format!("{code:0width$}", width = self.digits as usize)
+        }
+    }
+}
+
+fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(message);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Decode base32, ignoring case, whitespace and padding, as authenticator keys are often
+/// written in groups of four.
+fn decode_base32(input: &str) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| char::from(a) == c.to_ascii_uppercase())
+            .ok_or_else(|| eyre!("Invalid character in TOTP secret: {c}"))?;
+
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The ASCII secrets from RFC 6238's test vectors, in base32.
+    const RFC_SHA1: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    const RFC_SHA256: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA====";
+    const RFC_SHA512: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+                              GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNA=";
+
+    fn otpauth(secret: &str, algorithm: &str) -> Totp {
+        format!("otpauth://totp/Example:alice?secret={secret}&algorithm={algorithm}&digits=8")
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn rfc_6238_vectors() {
+        let sha1 = otpauth(RFC_SHA1, "SHA1");
+        let sha256 = otpauth(RFC_SHA256, "SHA256");
+        let sha512 = otpauth(RFC_SHA512, "SHA512");
+
+        for (timestamp, expected) in [
+            (59, ["94287082", "46119246", "90693936"]),
+            (1_111_111_109, ["07081804", "68084774", "25091201"]),
+            (1_111_111_111, ["14050471", "67062674", "99943326"]),
+            (1_234_567_890, ["89005924", "91819424", "93441116"]),
+            (2_000_000_000, ["69279037", "90698825", "38618901"]),
+            (20_000_000_000, ["65353130", "77737706", "47863826"]),
+        ] {
+            assert_eq!(
+                [
+                    sha1.at(timestamp),
+                    sha256.at(timestamp),
+                    sha512.at(timestamp)
+                ],
+                expected,
+                "at {timestamp}"
+            );
+        }
+    }
+
+    #[test]
+    fn steam_codes() {
+        let totp = "steam://JBSWY3DPEHPK3PXP".parse::<Totp>().unwrap();
+        assert_eq!(totp.at(59), "2YXGV");
+        assert_eq!(totp.at(1_700_000_000), "2KM2P");
+
+        let totp = "otpauth://totp/Steam:alice?secret=JBSWY3DPEHPK3PXP&encoder=steam"
+            .parse::<Totp>()
+            .unwrap();
+        assert_eq!(totp.at(1_700_000_000), "2KM2P");
+    }
+
+    #[test]
+    fn bare_secrets_use_the_defaults() {
+        let totp = "jbsw y3dp ehpk 3pxp".parse::<Totp>().unwrap();
+
+        assert_eq!(totp.algorithm, Algorithm::Sha1);
+        assert_eq!(totp.digits, 6);
+        assert_eq!(totp.period, 30);
+        assert!(!totp.steam);
+        assert_eq!(totp.at(1_700_000_000), "324550");
+    }
+
+    #[test]
+    fn otpauth_parameters_are_parsed() {
+        let totp = "OTPAUTH://totp/Example:alice?Secret=JBSWY3DPEHPK3PXP&algorithm=sha256&digits=7&period=60&issuer=Example"
+            .parse::<Totp>()
+            .unwrap();
+
+        assert_eq!(totp.algorithm, Algorithm::Sha256);
+        assert_eq!(totp.digits, 7);
+        assert_eq!(totp.period, 60);
+        assert_eq!(totp.at(1_700_000_000), "1205722");
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected() {
+        for key in [
+            "",
+            "not base32!",
+            "otpauth://totp/Example:alice?digits=6",
+            "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&algorithm=MD5",
+            "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&digits=0",
+            "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&digits=11",
+            "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&period=0",
+        ] {
+            assert!(key.parse::<Totp>().is_err(), "{key}");
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! `bwrsd` as `bwrs` starts it on demand, with the settings from `bwrs config`.
+
+use std::{
+    fs,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bwrs::{
+    agent::{read_message, write_message, Request, Response},
+    crypto::SymmetricKey,
+};
+use serde_json::json;
+
+const ACCOUNT: &str = "default";
+
+/// A data directory holding one unlocked account, with an empty vault cache.
+struct Fixture {
+    dir: PathBuf,
+    session: SymmetricKey,
+}
+impl Fixture {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("bwrs-agent-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let user_key = SymmetricKey::generate();
+        let session = SymmetricKey::generate();
+
+        let profile = dir.join("data").join("accounts").join(ACCOUNT);
+        fs::create_dir_all(&profile).unwrap();
+        let account = json!({
+            "email": "user@example.com",
+            "kdf": { "kdf": 0, "kdfIterations": 600000 },
+            "access_token": "token",
+            "refresh_token": null,
+            "key": null,
+            "private_key": null,
+            "protected_key": session.encrypt(user_key.as_bytes()).to_string(),
+        });
+        fs::write(profile.join("data.json"), account.to_string()).unwrap();
+        // Nothing listens here, so the agent's notifications hub never reaches a real server.
+        let server = json!({ "url": "http://127.0.0.1:1" });
+        fs::write(profile.join("server.json"), server.to_string()).unwrap();
+        let vault = json!({
+            "profile": { "organizations": [] },
+            "ciphers": [],
+            "folders": [],
+            "policies": [],
+        });
+        fs::write(profile.join("vault.json"), vault.to_string()).unwrap();
+
+        Self { dir, session }
+    }
+
+    fn command(&self, program: &str) -> Command {
+        let mut command = Command::new(program);
+        command
+            .env("BWRS_DATA_DIR", self.dir.join("data"))
+            .env("BWRS_AGENT_SOCK", self.dir.join("agent.sock"))
+            .env("XDG_STATE_HOME", self.dir.join("state"))
+            .env_remove("BW_SESSION");
+
+        command
+    }
+
+    fn bwrs(&self, args: &[&str]) -> Output {
+        let output = self
+            .command(env!("CARGO_BIN_EXE_bwrs"))
+            .args(args)
+            .env("BW_SESSION", self.session.to_base64())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+
+        output
+    }
+
+    fn has_keys(&self) -> bool {
+        let mut stream = UnixStream::connect(self.dir.join("agent.sock")).unwrap();
+        let request = Request::HasKeys {
+            account: ACCOUNT.to_string(),
+        };
+        write_message(&mut stream, &request).unwrap();
+
+        match read_message(&mut stream).unwrap().unwrap() {
+            Response::Ok => true,
+            Response::Locked => false,
+            response => panic!("Unexpected response {response:?}"),
+        }
+    }
+}
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = self
+            .command(env!("CARGO_BIN_EXE_bwrsd"))
+            .arg("--stop")
+            .output();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn config(dir: &Path) -> serde_json::Value {
+    serde_json::from_slice(&fs::read(dir.join("data").join("config.json")).unwrap()).unwrap()
+}
+
+#[test]
+fn auto_started_agent_expires_keys() {
+    let fixture = Fixture::new();
+
+    assert_eq!(
+        fixture.bwrs(&["config", "agent-idle-timeout"]).stdout,
+        b"900\n"
+    );
+    fixture.bwrs(&["config", "agent-idle-timeout", "1"]);
+    fixture.bwrs(&["config", "agent-sync-interval", "0"]);
+    assert_eq!(
+        fixture.bwrs(&["config", "agent-idle-timeout"]).stdout,
+        b"1\n"
+    );
+    assert_eq!(config(&fixture.dir)["agent_idle_timeout"], 1);
+
+    // Reading the vault with BW_SESSION starts the agent and hands it the key.
+    fixture.bwrs(&["list", "items"]);
+    assert!(fixture.has_keys());
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while fixture.has_keys() {
+        assert!(Instant::now() < deadline, "the key never expired");
+        thread::sleep(Duration::from_millis(100));
+    }
+}
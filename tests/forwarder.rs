@@ -0,0 +1,405 @@
+//! The forwarded email providers, checked against a local server standing in for each service.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread::{self, JoinHandle},
+};
+
+use bwrs::{
+    crypto::Base64,
+    forwarder::{
+        AddyIo, DuckDuckGo, Fastmail, FirefoxRelay, ForwardEmail, ForwardedEmailProvider,
+        SimpleLogin,
+    },
+};
+use serde_json::{json, Value};
+
+const TOKEN: &str = "tok";
+const MASKED_EMAIL: &str = "https://www.fastmail.com/dev/maskedemail";
+
+/// Makes a provider which talks to the given URL.
+type Provider = fn(String) -> Box<dyn ForwardedEmailProvider>;
+
+/// A request as the mock server received it.
+#[derive(Debug)]
+struct Received {
+    method: String,
+    path: String,
+    /// Keyed by lowercased name.
+    headers: HashMap<String, String>,
+    body: Value,
+}
+impl Received {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+/// Serve one connection per response, in order, returning what was requested. `responses` is
+/// given the server's URL, for responses which point back at it.
+fn serve(responses: impl FnOnce(&str) -> Vec<(u16, Value)>) -> (String, JoinHandle<Vec<Received>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let responses = responses(&url);
+
+    let server = thread::spawn(move || {
+        responses
+            .into_iter()
+            .map(|(status, response)| {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap().to_string();
+                let path = parts.next().unwrap().to_string();
+
+                let mut headers = HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(':') else {
+                        break;
+                    };
+                    headers.insert(name.to_lowercase(), value.trim().to_string());
+                }
+                let length = headers
+                    .get("content-length")
+                    .map_or(0, |length| length.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let response = response.to_string();
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+
+                Received {
+                    method,
+                    path,
+                    headers,
+                    body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+                }
+            })
+            .collect()
+    });
+
+    (url, server)
+}
+
+/// Generate an alias for github.com against a single response, returning it and the request.
+fn generate(provider: Provider, status: u16, response: Value) -> (eyre::Result<String>, Received) {
+    let (url, server) = serve(|_| vec![(status, response)]);
+    let alias = provider(url).generate(Some("github.com"));
+    let mut received = server.join().unwrap();
+
+    (alias, received.remove(0))
+}
+
+fn simplelogin(base_url: String) -> Box<dyn ForwardedEmailProvider> {
+    Box::new(SimpleLogin {
+        base_url,
+        api_key: TOKEN.to_string(),
+    })
+}
+fn addy_io(base_url: String) -> Box<dyn ForwardedEmailProvider> {
+    Box::new(AddyIo {
+        base_url,
+        api_token: TOKEN.to_string(),
+        domain: "anonaddy.me".to_string(),
+    })
+}
+fn firefox_relay(base_url: String) -> Box<dyn ForwardedEmailProvider> {
+    Box::new(FirefoxRelay {
+        base_url,
+        api_token: TOKEN.to_string(),
+    })
+}
+fn fastmail(base_url: String) -> Box<dyn ForwardedEmailProvider> {
+    Box::new(Fastmail {
+        base_url,
+        api_token: TOKEN.to_string(),
+    })
+}
+fn duckduckgo(base_url: String) -> Box<dyn ForwardedEmailProvider> {
+    Box::new(DuckDuckGo {
+        base_url,
+        api_token: TOKEN.to_string(),
+    })
+}
+fn forward_email(base_url: String) -> Box<dyn ForwardedEmailProvider> {
+    Box::new(ForwardEmail {
+        base_url,
+        api_token: TOKEN.to_string(),
+        domain: "example.com".to_string(),
+    })
+}
+
+#[test]
+fn simplelogin_creates_random_aliases() {
+    let (alias, request) = generate(
+        simplelogin,
+        201,
+        json!({ "alias": "random.word123@simplelogin.com" }),
+    );
+
+    assert_eq!(alias.unwrap(), "random.word123@simplelogin.com");
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/api/alias/random/new?hostname=github.com");
+    assert_eq!(request.header("authentication"), Some(TOKEN));
+    assert_eq!(
+        request.body,
+        json!({ "note": "Website: github.com. Generated by bwrs." })
+    );
+}
+
+#[test]
+fn addy_io_creates_aliases_at_the_domain() {
+    let (alias, request) = generate(
+        addy_io,
+        201,
+        json!({ "data": { "email": "abc123@anonaddy.me" } }),
+    );
+
+    assert_eq!(alias.unwrap(), "abc123@anonaddy.me");
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/api/v1/aliases");
+    assert_eq!(request.header("authorization"), Some("Bearer tok"));
+    assert_eq!(request.header("x-requested-with"), Some("XMLHttpRequest"));
+    assert_eq!(
+        request.body,
+        json!({
+            "domain": "anonaddy.me",
+            "description": "Website: github.com. Generated by bwrs.",
+        })
+    );
+}
+
+#[test]
+fn addy_io_needs_a_domain() {
+    let addy_io = AddyIo::new(TOKEN.to_string(), " ".to_string());
+
+    let error = addy_io.generate(None).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "addy.io needs a domain to make the alias at."
+    );
+}
+
+#[test]
+fn firefox_relay_creates_relay_addresses() {
+    let (alias, request) = generate(
+        firefox_relay,
+        201,
+        json!({ "full_address": "xyz@mozmail.com" }),
+    );
+
+    assert_eq!(alias.unwrap(), "xyz@mozmail.com");
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/api/v1/relayaddresses/");
+    assert_eq!(request.header("authorization"), Some("Token tok"));
+    assert_eq!(
+        request.body,
+        json!({
+            "enabled": true,
+            "generated_for": "github.com",
+            "description": "Website: github.com. Generated by bwrs.",
+        })
+    );
+}
+
+#[test]
+fn fastmail_creates_masked_emails_over_jmap() {
+    let (url, server) = serve(|url| {
+        vec![
+            (
+                200,
+                json!({
+                    "apiUrl": format!("{url}/jmap/api/"),
+                    "primaryAccounts": { MASKED_EMAIL: "u123" },
+                }),
+            ),
+            (
+                200,
+                json!({
+                    "methodResponses": [[
+                        "MaskedEmail/set",
+                        { "created": { "new-masked-email": { "email": "masked.1@fastmail.com" } } },
+                        "0",
+                    ]],
+                }),
+            ),
+        ]
+    });
+
+    let alias = fastmail(url).generate(Some("github.com"));
+    let requests = server.join().unwrap();
+
+    assert_eq!(alias.unwrap(), "masked.1@fastmail.com");
+    let (session, set) = (&requests[0], &requests[1]);
+    assert_eq!(session.method, "GET");
+    assert_eq!(session.path, "/jmap/session");
+    assert_eq!(session.header("authorization"), Some("Bearer tok"));
+    assert_eq!(set.method, "POST");
+    assert_eq!(set.path, "/jmap/api/");
+    assert_eq!(set.header("authorization"), Some("Bearer tok"));
+    assert_eq!(
+        set.body,
+        json!({
+            "using": ["urn:ietf:params:jmap:core", MASKED_EMAIL],
+            "methodCalls": [[
+                "MaskedEmail/set",
+                {
+                    "accountId": "u123",
+                    "create": {
+                        "new-masked-email": {
+                            "state": "enabled",
+                            "description": "",
+                            "forDomain": "github.com",
+                        },
+                    },
+                },
+                "0",
+            ]],
+        })
+    );
+}
+
+#[test]
+fn fastmail_reports_masked_emails_which_were_not_created() {
+    let (url, server) = serve(|url| {
+        vec![
+            (
+                200,
+                json!({
+                    "apiUrl": format!("{url}/jmap/api/"),
+                    "primaryAccounts": { MASKED_EMAIL: "u123" },
+                }),
+            ),
+            (
+                200,
+                json!({
+                    "methodResponses": [[
+                        "MaskedEmail/set",
+                        {
+                            "notCreated": {
+                                "new-masked-email": {
+                                    "type": "invalidProperties",
+                                    "description": "forDomain is bad",
+                                },
+                            },
+                        },
+                        "0",
+                    ]],
+                }),
+            ),
+        ]
+    });
+
+    let error = fastmail(url).generate(Some("github.com")).unwrap_err();
+    server.join().unwrap();
+
+    assert_eq!(
+        error.to_string(),
+        "Fastmail didn't make a masked email: forDomain is bad"
+    );
+}
+
+#[test]
+fn fastmail_needs_a_masked_email_account() {
+    let (url, server) = serve(|url| {
+        vec![(
+            200,
+            json!({ "apiUrl": format!("{url}/jmap/api/"), "primaryAccounts": {} }),
+        )]
+    });
+
+    let error = fastmail(url).generate(None).unwrap_err();
+    server.join().unwrap();
+
+    assert_eq!(
+        error.to_string(),
+        "This Fastmail API token can't make masked emails."
+    );
+}
+
+#[test]
+fn duckduckgo_creates_duck_addresses() {
+    let (alias, request) = generate(duckduckgo, 201, json!({ "address": "quack-quack" }));
+
+    assert_eq!(alias.unwrap(), "quack-quack@duck.com");
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/api/email/addresses");
+    assert_eq!(request.header("authorization"), Some("Bearer tok"));
+}
+
+#[test]
+fn forward_email_creates_aliases_at_the_domain() {
+    let (alias, request) = generate(
+        forward_email,
+        200,
+        json!({ "name": "fwd42", "domain": { "name": "example.com" } }),
+    );
+
+    assert_eq!(alias.unwrap(), "fwd42@example.com");
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/v1/domains/example.com/aliases");
+    let basic = format!("Basic {}", Base64::encode(format!("{TOKEN}:")));
+    assert_eq!(request.header("authorization"), Some(basic.as_str()));
+    assert_eq!(
+        request.body,
+        json!({
+            "labels": "github.com",
+            "description": "Website: github.com. Generated by bwrs.",
+        })
+    );
+}
+
+#[test]
+fn rejected_tokens_are_invalid() {
+    let providers: [(Provider, &str); 6] = [
+        (simplelogin, "SimpleLogin"),
+        (addy_io, "addy.io"),
+        (firefox_relay, "Firefox Relay"),
+        (fastmail, "Fastmail"),
+        (duckduckgo, "DuckDuckGo"),
+        (forward_email, "Forward Email"),
+    ];
+
+    for (provider, name) in providers {
+        for status in [401, 403] {
+            let (alias, _) = generate(provider, status, json!({ "error": "Unauthorized" }));
+
+            assert_eq!(
+                alias.unwrap_err().to_string(),
+                format!("Invalid {name} API token.")
+            );
+        }
+    }
+}
+
+#[test]
+fn other_failures_carry_the_providers_reason() {
+    let (alias, _) = generate(
+        addy_io,
+        422,
+        json!({ "message": "The selected domain is invalid." }),
+    );
+    assert_eq!(
+        alias.unwrap_err().to_string(),
+        "addy.io failed to make an alias: The selected domain is invalid."
+    );
+
+    let (alias, _) = generate(firefox_relay, 500, Value::Null);
+    assert_eq!(
+        alias.unwrap_err().to_string(),
+        "Firefox Relay failed to make an alias: 500 Internal Server Error"
+    );
+}
@@ -0,0 +1,630 @@
+//! `bwrsd --secret-service`, checked with a libsecret-style client on a private session bus.
+#![cfg(feature = "dbus")]
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use bwrs::{
+    agent::{read_message, write_message, Request, Response},
+    crypto::SymmetricKey,
+};
+use cbc::cipher::block_padding::Pkcs7;
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, RngCore};
+use rsa::BigUint;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use zbus::{
+    blocking::{fdo::DBusProxy, proxy::Builder, Connection, Proxy},
+    proxy::CacheProperties,
+    zvariant::{OwnedObjectPath, OwnedValue, Value as Variant},
+};
+use zeroize::Zeroizing;
+
+const ACCOUNT: &str = "work";
+const GITHUB_ID: &str = "10000000-0000-0000-0000-000000000001";
+const EXAMPLE_ID: &str = "20000000-0000-0000-0000-000000000002";
+const CREATED_ID: &str = "30000000-0000-0000-0000-000000000003";
+const COLLECTION: &str = "/org/freedesktop/secrets/collection/work";
+
+const DH_PRIME: &str = "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74\
+                        020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437\
+                        4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED\
+                        EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE65381FFFFFFFFFFFFFFFF";
+
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// A request as the mock API server received it.
+#[derive(Debug)]
+struct Received {
+    method: String,
+    path: String,
+    body: Value,
+}
+
+/// A session bus and a `bwrsd` serving the secret service on it, with one account whose vault
+/// cache holds two logins.
+struct Fixture {
+    dir: PathBuf,
+    vault: Value,
+    bus: Child,
+    agent: Option<Child>,
+    connection: Connection,
+    user_key: SymmetricKey,
+}
+impl Fixture {
+    /// Start the bus and an agent with extra `args`, or `None` if `dbus-daemon` isn't installed.
+    fn start(name: &str, args: &[&str]) -> Option<Self> {
+        let dir =
+            std::env::temp_dir().join(format!("bwrs-secret-service-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let user_key = SymmetricKey::generate();
+        let vault = write_vault(&dir, &user_key);
+
+        let mut bus = match Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(bus) => bus,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!("Skipping: dbus-daemon isn't installed");
+                return None;
+            }
+            Err(e) => panic!("Failed to start dbus-daemon: {e}"),
+        };
+        let mut address = String::new();
+        BufReader::new(bus.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim().to_string();
+
+        let agent = Command::new(env!("CARGO_BIN_EXE_bwrsd"))
+            .args([
+                "--secret-service",
+                "--sync-interval",
+                "0",
+                "--no-notifications",
+            ])
+            .args(args)
+            .env("DBUS_SESSION_BUS_ADDRESS", &address)
+            .env("BWRS_DATA_DIR", dir.join("data"))
+            .env("BWRS_AGENT_SOCK", dir.join("agent.sock"))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let connection = zbus::blocking::connection::Builder::address(address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let fixture = Self {
+            dir,
+            vault,
+            bus,
+            agent: Some(agent),
+            connection,
+            user_key,
+        };
+
+        let bus = DBusProxy::new(&fixture.connection).unwrap();
+        wait_for(|| {
+            bus.name_has_owner("org.freedesktop.secrets".try_into().unwrap())
+                .unwrap()
+        });
+
+        Some(fixture)
+    }
+
+    fn agent(&self, request: &Request) -> Response {
+        let mut stream = UnixStream::connect(self.dir.join("agent.sock")).unwrap();
+        write_message(&mut stream, request).unwrap();
+        read_message(&mut stream).unwrap().unwrap()
+    }
+
+    fn unlock(&self) {
+        let response = self.agent(&Request::Unlock {
+            account: ACCOUNT.to_string(),
+            user_key: Zeroizing::new(self.user_key.to_base64()),
+            org_keys: Default::default(),
+        });
+        assert!(matches!(response, Response::Ok), "{response:?}");
+    }
+
+    /// Log the account in to a mock API server, which accepts every change and records it.
+    fn log_in(&self) -> Arc<Mutex<Vec<Received>>> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let profile = self.dir.join("data").join("accounts").join(ACCOUNT);
+        fs::write(
+            profile.join("server.json"),
+            json!({ "url": url }).to_string(),
+        )
+        .unwrap();
+        fs::write(
+            profile.join("data.json"),
+            json!({
+                "email": "user@example.com",
+                "kdf": { "kdf": 0, "kdfIterations": 600000 },
+                "access_token": "token",
+                "refresh_token": null,
+                "key": null,
+                "private_key": null,
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let ciphers = self.vault["ciphers"].as_array().unwrap().clone();
+        thread::spawn({
+            let received = Arc::clone(&received);
+            move || {
+                for stream in listener.incoming() {
+                    let request = serve(stream.unwrap(), &ciphers);
+                    received.lock().unwrap().push(request);
+                }
+            }
+        });
+
+        received
+    }
+
+    fn service(&self) -> Proxy<'_> {
+        Proxy::new(
+            &self.connection,
+            "org.freedesktop.secrets",
+            "/org/freedesktop/secrets",
+            "org.freedesktop.Secret.Service",
+        )
+        .unwrap()
+    }
+
+    /// A proxy whose properties are only updated by `PropertiesChanged` signals.
+    fn cached(&self, path: &str, interface: &str) -> Proxy<'_> {
+        Builder::new(&self.connection)
+            .destination("org.freedesktop.secrets")
+            .unwrap()
+            .path(path.to_string())
+            .unwrap()
+            .interface(interface.to_string())
+            .unwrap()
+            .cache_properties(CacheProperties::Yes)
+            .build()
+            .unwrap()
+    }
+
+    fn search(&self, attributes: &[(&str, &str)]) -> Vec<OwnedObjectPath> {
+        let attributes = attributes.iter().copied().collect::<HashMap<_, _>>();
+        let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) =
+            self.service().call("SearchItems", &(attributes,)).unwrap();
+        assert!(locked.is_empty());
+
+        unlocked
+    }
+
+    fn open_session(&self) -> OwnedObjectPath {
+        let (_, session): (OwnedValue, OwnedObjectPath) = self
+            .service()
+            .call("OpenSession", &("plain", Variant::from("")))
+            .unwrap();
+
+        session
+    }
+
+    /// Open a `dh-ietf1024-sha256-aes128-cbc-pkcs7` session, returning it and its AES key.
+    fn open_dh_session(&self) -> (OwnedObjectPath, [u8; 16]) {
+        let prime = BigUint::parse_bytes(DH_PRIME.as_bytes(), 16).unwrap();
+        let mut private = [0; 128];
+        OsRng.fill_bytes(&mut private);
+        let private = BigUint::from_bytes_be(&private);
+        let public = BigUint::from(2u8).modpow(&private, &prime);
+        let (output, session): (OwnedValue, OwnedObjectPath) = self
+            .service()
+            .call(
+                "OpenSession",
+                &(
+                    "dh-ietf1024-sha256-aes128-cbc-pkcs7",
+                    Variant::from(public.to_bytes_be()),
+                ),
+            )
+            .unwrap();
+        let shared = BigUint::from_bytes_be(&Vec::<u8>::try_from(output).unwrap())
+            .modpow(&private, &prime)
+            .to_bytes_be();
+        let mut ikm = [0; 128];
+        ikm[128 - shared.len()..].copy_from_slice(&shared);
+        let mut key = [0; 16];
+        Hkdf::<Sha256>::new(None, &ikm)
+            .expand(&[], &mut key)
+            .unwrap();
+
+        (session, key)
+    }
+
+    fn get_secrets(
+        &self,
+        items: &[OwnedObjectPath],
+        session: &OwnedObjectPath,
+    ) -> HashMap<OwnedObjectPath, Secret> {
+        self.service()
+            .call("GetSecrets", &(items, session))
+            .unwrap()
+    }
+}
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        if let Some(mut agent) = self.agent.take() {
+            let _ = agent.kill();
+            let _ = agent.wait();
+        }
+        let _ = self.bus.kill();
+        let _ = self.bus.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Answer one request to the mock API server, echoing changed items back as the server would.
+fn serve(stream: std::net::TcpStream, ciphers: &[Value]) -> Received {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap().to_string();
+    let path = parts.next().unwrap().to_string();
+
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let Some((name, value)) = line.trim_end().split_once(':') else {
+            break;
+        };
+        if name.eq_ignore_ascii_case("content-length") {
+            length = value.trim().parse().unwrap();
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    let id = path.split('/').nth(3).unwrap_or_default();
+    let response = match (method.as_str(), path.as_str()) {
+        ("GET", "/api/config") => json!({}),
+        ("POST", "/api/ciphers") => json!({ "id": CREATED_ID }),
+        ("PUT", path) if path.ends_with("/delete") => Value::Null,
+        ("GET", path) if path.ends_with("/details") => {
+            let mut cipher = ciphers
+                .iter()
+                .find(|cipher| cipher["id"] == id)
+                .unwrap()
+                .clone();
+            cipher["deletedDate"] = "2024-06-01T12:00:00Z".into();
+            cipher
+        }
+        ("PUT", _) => json!({ "id": id }),
+        _ => panic!("Unexpected request: {method} {path}"),
+    };
+    // Created and updated items are the request with an id.
+    let response = match (response, &body) {
+        (Value::Object(mut response), Value::Object(request)) if response.len() == 1 => {
+            for (name, value) in request {
+                response.entry(name.clone()).or_insert(value.clone());
+            }
+            Value::Object(response)
+        }
+        (response, _) => response,
+    }
+    .to_string();
+
+    write!(
+        reader.get_mut(),
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{response}",
+        response.len()
+    )
+    .unwrap();
+
+    Received { method, path, body }
+}
+
+fn write_vault(dir: &std::path::Path, key: &SymmetricKey) -> Value {
+    let encrypt = |value: &str| key.encrypt(value.as_bytes()).to_string();
+    let login = |id: &str, name: &str, username: &str, password: &str, uri: &str| {
+        json!({
+            "id": id,
+            "organizationId": null,
+            "folderId": null,
+            "type": 1,
+            "name": encrypt(name),
+            "notes": null,
+            "login": {
+                "username": encrypt(username),
+                "password": encrypt(password),
+                "uris": [{ "uri": encrypt(uri), "match": null }],
+            },
+            "revisionDate": "2024-05-01T12:00:00Z",
+            "creationDate": "2024-04-01T12:00:00Z",
+            "deletedDate": null,
+        })
+    };
+    let vault = json!({
+        "ciphers": [
+            login(GITHUB_ID, "GitHub", "octocat", "hunter2", "https://github.com/login"),
+            login(EXAMPLE_ID, "Example", "alice", "correct horse", "https://example.com"),
+        ],
+        "policies": [],
+    });
+
+    let profile = dir.join("data").join("accounts").join(ACCOUNT);
+    fs::create_dir_all(&profile).unwrap();
+    fs::write(profile.join("vault.json"), vault.to_string()).unwrap();
+
+    vault
+}
+
+fn item(id: &str) -> OwnedObjectPath {
+    OwnedObjectPath::try_from(format!("{COLLECTION}/{}", id.replace('-', ""))).unwrap()
+}
+
+fn wait_for(mut f: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !f() {
+        assert!(Instant::now() < deadline, "timed out");
+        thread::sleep(Duration::from_millis(25));
+    }
+}
+
+fn wait_for_locked(proxy: &Proxy<'_>, locked: bool) {
+    wait_for(|| proxy.cached_property::<bool>("Locked").unwrap() == Some(locked));
+}
+
+fn proxy<'a>(fixture: &'a Fixture, path: &str, interface: &str) -> Proxy<'a> {
+    Proxy::new(
+        &fixture.connection,
+        "org.freedesktop.secrets",
+        path.to_string(),
+        interface.to_string(),
+    )
+    .unwrap()
+}
+
+/// The name of the D-Bus error a call failed with.
+fn error_name(error: zbus::Error) -> String {
+    match error {
+        zbus::Error::MethodError(name, ..) => name.to_string(),
+        e => panic!("Expected a method error: {e}"),
+    }
+}
+
+fn decrypt(key: &SymmetricKey, value: &Value) -> String {
+    key.decrypt_str(&value.as_str().unwrap().parse().unwrap())
+        .unwrap()
+}
+
+#[test]
+fn secret_service() {
+    let Some(fixture) = Fixture::start("read-only", &[]) else {
+        return;
+    };
+    let collection = fixture.cached(COLLECTION, "org.freedesktop.Secret.Collection");
+    let github = fixture.cached(item(GITHUB_ID).as_str(), "org.freedesktop.Secret.Item");
+
+    // Nothing can be matched while locked, since the attributes are encrypted.
+    wait_for_locked(&collection, true);
+    assert!(fixture.search(&[("username", "octocat")]).is_empty());
+
+    fixture.unlock();
+    wait_for_locked(&collection, false);
+    wait_for_locked(&github, false);
+    // The label is announced after the item is unlocked.
+    wait_for(|| {
+        github
+            .cached_property::<String>("Label")
+            .unwrap()
+            .as_deref()
+            == Some("GitHub")
+    });
+
+    assert_eq!(
+        fixture.search(&[("uri", "https://github.com/login")]),
+        [item(GITHUB_ID)]
+    );
+    assert_eq!(
+        fixture.search(&[
+            ("username", "alice"),
+            ("xdg:schema", "org.example.Password")
+        ]),
+        [item(EXAMPLE_ID)]
+    );
+    assert!(fixture
+        .search(&[("username", "octocat"), ("uri", "https://example.com")])
+        .is_empty());
+
+    // Plain sessions send the secret as is.
+    let plain = fixture.open_session();
+    let secrets = fixture.get_secrets(&[item(GITHUB_ID), item(EXAMPLE_ID)], &plain);
+    let (session, parameters, value, content_type) = &secrets[&item(GITHUB_ID)];
+    assert_eq!(session, &plain);
+    assert!(parameters.is_empty());
+    assert_eq!(value, b"hunter2");
+    assert_eq!(content_type, "text/plain; charset=utf8");
+    assert_eq!(secrets[&item(EXAMPLE_ID)].2, b"correct horse");
+
+    // DH sessions encrypt it with the negotiated key.
+    let (encrypted, key) = fixture.open_dh_session();
+    let secrets = fixture.get_secrets(&[item(GITHUB_ID)], &encrypted);
+    let (_, iv, value, _) = &secrets[&item(GITHUB_ID)];
+    assert_ne!(value, b"hunter2");
+    let decrypted = cbc::Decryptor::<aes::Aes128>::new(&key.into(), iv.as_slice().into())
+        .decrypt_padded_vec_mut::<Pkcs7>(value)
+        .unwrap();
+    assert_eq!(decrypted, b"hunter2");
+
+    // Nothing can be changed without --secret-service-writable.
+    let secret = (&plain, Vec::<u8>::new(), b"hunter3".to_vec(), "text/plain");
+    let error = github
+        .call::<_, _, ()>("SetSecret", &(secret,))
+        .unwrap_err();
+    assert_eq!(error_name(error), "org.freedesktop.DBus.Error.NotSupported");
+    let error = github
+        .call::<_, _, OwnedObjectPath>("Delete", &())
+        .unwrap_err();
+    assert_eq!(error_name(error), "org.freedesktop.DBus.Error.NotSupported");
+
+    // Locking through the agent is seen by clients, and hides the secrets again.
+    let response = fixture.agent(&Request::Lock {
+        account: Some(ACCOUNT.to_string()),
+    });
+    assert!(matches!(response, Response::Ok), "{response:?}");
+    wait_for_locked(&collection, true);
+    wait_for_locked(&github, true);
+    assert!(fixture.search(&[("username", "octocat")]).is_empty());
+    assert!(fixture.get_secrets(&[item(GITHUB_ID)], &plain).is_empty());
+}
+
+#[test]
+fn writable_secret_service() {
+    let Some(fixture) = Fixture::start("writable", &["--secret-service-writable"]) else {
+        return;
+    };
+    let received = fixture.log_in();
+    let collection = proxy(&fixture, COLLECTION, "org.freedesktop.Secret.Collection");
+    let github = proxy(
+        &fixture,
+        item(GITHUB_ID).as_str(),
+        "org.freedesktop.Secret.Item",
+    );
+    fixture.unlock();
+    let plain = fixture.open_session();
+
+    // New items are logins, with attributes other than the username and URI kept as fields.
+    let attributes = HashMap::from([
+        ("username", "bob"),
+        ("uri", "imaps://mail.example.com"),
+        ("service", "mail"),
+    ]);
+    let properties = HashMap::from([
+        ("org.freedesktop.Secret.Item.Label", Variant::from("Mail")),
+        (
+            "org.freedesktop.Secret.Item.Attributes",
+            Variant::from(attributes.clone()),
+        ),
+    ]);
+    let secret = (&plain, Vec::<u8>::new(), b"s3cret".to_vec(), "text/plain");
+    let (created, _): (OwnedObjectPath, OwnedObjectPath) = collection
+        .call("CreateItem", &(&properties, &secret, false))
+        .unwrap();
+    assert_eq!(created, item(CREATED_ID));
+    assert_eq!(fixture.search(&[("service", "mail")]), [item(CREATED_ID)]);
+    assert_eq!(
+        fixture.get_secrets(&[item(CREATED_ID)], &plain)[&created].2,
+        b"s3cret"
+    );
+
+    let request = received.lock().unwrap().pop().unwrap();
+    assert_eq!(
+        (request.method.as_str(), request.path.as_str()),
+        ("POST", "/api/ciphers")
+    );
+    let cipher = &request.body;
+    assert_eq!(decrypt(&fixture.user_key, &cipher["name"]), "Mail");
+    assert_eq!(
+        decrypt(&fixture.user_key, &cipher["login"]["username"]),
+        "bob"
+    );
+    assert_eq!(
+        decrypt(&fixture.user_key, &cipher["login"]["uris"][0]["uri"]),
+        "imaps://mail.example.com"
+    );
+    assert_eq!(
+        decrypt(&fixture.user_key, &cipher["fields"][0]["name"]),
+        "service"
+    );
+    assert_eq!(
+        decrypt(&fixture.user_key, &cipher["fields"][0]["value"]),
+        "mail"
+    );
+
+    // Replacing changes the matching item, remembering the old password.
+    let secret = (&plain, Vec::<u8>::new(), b"n3w".to_vec(), "text/plain");
+    let (replaced, _): (OwnedObjectPath, OwnedObjectPath) = collection
+        .call("CreateItem", &(&properties, &secret, true))
+        .unwrap();
+    assert_eq!(replaced, created);
+    assert_eq!(
+        fixture.get_secrets(&[item(CREATED_ID)], &plain)[&created].2,
+        b"n3w"
+    );
+
+    let request = received.lock().unwrap().pop().unwrap();
+    assert_eq!(request.method, "PUT");
+    assert_eq!(request.path, format!("/api/ciphers/{CREATED_ID}"));
+    let history = &request.body["passwordHistory"];
+    assert_eq!(
+        decrypt(&fixture.user_key, &history[0]["password"]),
+        "s3cret"
+    );
+
+    // Secrets sent over DH sessions are decrypted first.
+    let (encrypted, key) = fixture.open_dh_session();
+    let mut iv = [0; 16];
+    OsRng.fill_bytes(&mut iv);
+    let value = cbc::Encryptor::<aes::Aes128>::new(&key.into(), &iv.into())
+        .encrypt_padded_vec_mut::<Pkcs7>(b"hunter3");
+    let secret = (&encrypted, iv.to_vec(), value, "text/plain");
+    github.call::<_, _, ()>("SetSecret", &(secret,)).unwrap();
+    assert_eq!(
+        fixture.get_secrets(&[item(GITHUB_ID)], &plain)[&item(GITHUB_ID)].2,
+        b"hunter3"
+    );
+
+    let request = received.lock().unwrap().pop().unwrap();
+    assert_eq!(request.path, format!("/api/ciphers/{GITHUB_ID}"));
+    assert_eq!(
+        decrypt(&fixture.user_key, &request.body["login"]["password"]),
+        "hunter3"
+    );
+
+    // Deleted items go to the trash, and leave the collection.
+    let example = proxy(
+        &fixture,
+        item(EXAMPLE_ID).as_str(),
+        "org.freedesktop.Secret.Item",
+    );
+    example
+        .call::<_, _, OwnedObjectPath>("Delete", &())
+        .unwrap();
+    assert!(fixture.search(&[("username", "alice")]).is_empty());
+    let paths = received
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|request| format!("{} {}", request.method, request.path))
+        .collect::<Vec<_>>();
+    assert!(paths.contains(&format!("PUT /api/ciphers/{EXAMPLE_ID}/delete")));
+
+    // Locked items can't be changed.
+    let response = fixture.agent(&Request::Lock {
+        account: Some(ACCOUNT.to_string()),
+    });
+    assert!(matches!(response, Response::Ok), "{response:?}");
+    let secret = (&plain, Vec::<u8>::new(), b"hunter4".to_vec(), "text/plain");
+    let error = github
+        .call::<_, _, ()>("SetSecret", &(secret,))
+        .unwrap_err();
+    assert_eq!(error_name(error), "org.freedesktop.Secret.Error.IsLocked");
+}